@@ -3,11 +3,32 @@ extern crate tokio;
 #[macro_use]
 extern crate clap;
 
-use paper_api::{PaperClientDebug, PaperClientConfig, PaperClient};
+use paper_api::{PaperClientDebug, PaperClientConfig, PaperClient, post_json};
 use clap::ArgMatches;
-use paper_api::paper::{ChangesInfo, BuildDownloadRequest};
+use paper_api::paper::{BuildChannel, BuildInfo, ChangesInfo, parse_instant, validate_download_name};
+use paper_api::mock::{MockFixture, MockOptions, MockServer};
+use paper_api::mirror::{MirrorOptions, MirrorServer, MirrorStore, mirror_sync};
+use paper_api::classify::classify;
+use paper_api::index::BuildIndex;
+use paper_api::fleet::{ServerStatus, expand_pattern, fleet_report};
+use paper_api::rollout::{FleetConfig, PlannedAction, ServerOutcome, apply_fleet, plan_fleet, release_server, rollback_server};
+use paper_api::provenance::{VerificationSource, download_build, verify_jar, verify_jar_indexed};
+use paper_api::scaffold::{GcPreset, InitOptions, default_user, init_server};
+use paper_api::store::{GcReport, JarStore, LinkMode, StoredJar, locked_hashes};
+use paper_api::feed::{FeedFormat, build_feed};
+use paper_api::matrix::{CompatibilityMatrix, compatibility_matrix};
+use paper_api::notes::{NotesFormat, NotesOptions, render_notes};
+use paper_api::resolve::resolve_at;
+use paper_api::search::{SearchPattern, SearchQuery, search_group_changes, search_version_changes};
+use paper_api::stats::{StatsReport, group_stats, version_stats};
+use paper_api::support::{SupportPolicy, project_support, version_support};
+use paper_api::identify::{identify_jar, identify_jar_indexed};
+use paper_api::java::{enforce_java, required_java};
+use paper_api::version::VersionSpec;
+use paper_api::watch::{BuildWatch, WatchState};
+use std::net::IpAddr;
 use std::path::Path;
-use std::io::Write;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> paper_api::Result<()> {
@@ -17,6 +38,7 @@ async fn main() -> paper_api::Result<()> {
         (author: "Corey Shupe")
         (about: "Command interface to paper's API.")
         (@arg debug: -d --debug "Denotes if there should be debug generated.")
+        (@arg url: -u --url +takes_value +global "The base url of the API. Default: https://papermc.io/api")
         (@subcommand projects =>
             (about: "Gathers a list of projects supported by paper.")
         )
@@ -26,6 +48,52 @@ async fn main() -> paper_api::Result<()> {
             (@arg project: -p --project +takes_value +required "The project to target.")
             (@arg version: -v --version +takes_value "The project to download. Default: latest")
             (@arg build: -b --build +takes_value "The build to target (number). Default: latest")
+            (@arg create_dirs: --("create-dirs") "Creates missing parent directories of the path.")
+            (@arg as_of: --("as-of") +takes_value conflicts_with[build] "Downloads the newest build released by this date or time, e.g. 2021-06-01.")
+            (@arg provenance: --provenance "Writes a <jar>.paper.json sidecar recording where the jar came from.")
+            (@arg store: -s --store +takes_value "A jar store the jar is downloaded into once and linked from.")
+            (@arg link: --link +takes_value requires[store] "How the jar is placed from the store: auto, hardlink, reflink (GNU cp) or copy. Default: auto")
+            (@arg java: --java +takes_value "The java executable to check against the version, only warned about when missing. Default: java")
+            (@arg ignore_java: --("ignore-java") "Downloads even if the java executable is too old for the version.")
+        )
+        (@subcommand watch =>
+            (about: "Polls a version for new builds, running hooks for each one.")
+            (@arg project: -p --project +takes_value +required "The project to watch.")
+            (@arg version: -v --version +takes_value +required "The versions to watch, e.g. 1.16.5, 1.16.x or latest.")
+            (@arg interval: -i --interval +takes_value "The time between polls, e.g. 30s, 10m or 1h. Default: 10m")
+            (@arg exec: -e --exec +takes_value "A command to run for every new build.")
+            (@arg webhook: -w --webhook +takes_value "A url every new build is posted to as JSON, failures are logged and not retried.")
+            (@arg state: -s --state +takes_value "The file the last seen builds are kept in. Default: paper-watch.json")
+            (@arg once: --once "Polls once and exits.")
+        )
+        (@subcommand feed =>
+            (about: "Renders the builds of version groups as an Atom or RSS feed.")
+            (@arg source: -s --source +takes_value +required +multiple number_of_values(1) "A project and version group, e.g. paper:1.16. May be repeated.")
+            (@arg format: -f --format +takes_value "The feed format, atom or rss. Default: atom")
+            (@arg limit: -l --limit +takes_value "The maximum number of entries. Default: 50")
+            (@arg out: -o --out +takes_value "The file to write the feed to. Default: stdout")
+        )
+        (@subcommand notes =>
+            (about: "Renders the changes of builds as Markdown or HTML release notes.")
+            (@arg project: -p --project +takes_value +required "The project to target.")
+            (@group vtype +required =>
+                (@arg group: -g --group +takes_value "Defines a version group.")
+                (@arg version: -v --version +takes_value "Defines a version.")
+            )
+            (@arg since: -s --since +takes_value "Only includes builds after this build number.")
+            (@arg format: -f --format +takes_value "The notes format, markdown or html. Default: markdown")
+            (@arg repo: -r --repo +takes_value "The repository commits and issues link to. Default: https://github.com/PaperMC/<project name>")
+            (@arg out: -o --out +takes_value "The file to write the notes to. Default: stdout")
+        )
+        (@subcommand stats =>
+            (about: "Reports the build cadence of a version group or version.")
+            (@arg project: -p --project +takes_value +required "The project to target.")
+            (@group vtype +required =>
+                (@arg group: -g --group +takes_value "Defines a version group.")
+                (@arg version: -v --version +takes_value "Defines a version.")
+            )
+            (@arg top: -t --top +takes_value "The number of most changed weeks to show. Default: 5")
+            (@arg json: --json "Prints the report as JSON.")
         )
         (@subcommand project =>
             (about: "Gathers project information of a specific project.")
@@ -34,12 +102,138 @@ async fn main() -> paper_api::Result<()> {
                 (@arg group: -g --group +takes_value "Defines a version group.")
                 (@arg version: -v --version +takes_value "Defines a version.")
             )
+            (@arg eol_days: --("eol-days") +takes_value "Days without builds after which a version is unsupported. Default: 90")
+            (@arg superseded: --superseded "Also flags versions with a newer release as unsupported.")
             (@subcommand builds =>
                 (about: "Gathers the list of builds.")
                 (@arg build: -b --build +takes_value "The targeted build.")
             )
         )
-    ).get_matches();
+        (@subcommand matrix =>
+            (about: "Shows which versions and version groups several projects support, with their latest builds.")
+            (@arg project: -p --project +takes_value +required +multiple number_of_values(1) "A project to compare, e.g. paper. May be repeated.")
+            (@arg json: --json "Prints the matrix as JSON.")
+        )
+        (@subcommand apply =>
+            (about: "Updates the servers of a fleet config according to their policies, in batches.")
+            (@arg config: -c --config +takes_value "The fleet config. Default: fleet.json")
+            (@arg dry_run: --("dry-run") "Only prints what would be updated.")
+            (@arg json: --json "Prints the outcome as JSON.")
+        )
+        (@subcommand rollback =>
+            (about: "Restores the previous jar of a server of a fleet config.")
+            (@arg config: -c --config +takes_value "The fleet config. Default: fleet.json")
+            (@arg server: +required "The name of the server to roll back.")
+        )
+        (@subcommand release =>
+            (about: "Lets apply update a server of a fleet config again after a rollback held it.")
+            (@arg config: -c --config +takes_value "The fleet config. Default: fleet.json")
+            (@arg server: +required "The name of the server to release.")
+        )
+        (@subcommand init =>
+            (about: "Scaffolds a server directory: the jar, a lockfile, start scripts and, if accepted, the EULA.")
+            (@arg project: -p --project +takes_value "The project to install. Default: paper")
+            (@arg version: -v --version +takes_value "The versions to pick the newest of, e.g. 1.16.x. Default: latest")
+            (@arg experimental: --experimental "Allows experimental builds.")
+            (@arg jar: --jar +takes_value "The jar file name. Default: server.jar")
+            (@arg memory: -m --memory +takes_value "The maximum heap, e.g. 4G. Default: 2G")
+            (@arg min_memory: --("min-memory") +takes_value "The initial heap. Default: the maximum heap")
+            (@arg gc: --gc +takes_value "The GC flag preset, aikar or none. Default: aikar")
+            (@arg jvm_flag: --("jvm-flag") +takes_value +multiple number_of_values(1) allow_hyphen_values(true) "An extra JVM flag, e.g. -Dfile.encoding=UTF-8. May be repeated.")
+            (@arg java: --java +takes_value "The java executable the scripts run, checked against the version. Default: java")
+            (@arg ignore_java: --("ignore-java") "Installs even if the java executable is too old for the version.")
+            (@arg accept_eula: --("accept-eula") "Agrees to the Minecraft EULA (https://aka.ms/MinecraftEULA) by writing eula.txt.")
+            (@arg store: -s --store +takes_value "A jar store the jar is linked from.")
+            (@arg link: --link +takes_value requires[store] "How the jar is placed from the store: auto, hardlink, reflink (GNU cp) or copy. Default: auto")
+            (@arg force: --force "Overwrites an already initialized directory.")
+            (@arg user: --user +takes_value "The user the systemd unit runs the server as. Default: $SUDO_USER, else $USER")
+            (@arg group: --group +takes_value "The group the systemd unit runs the server as.")
+            (@arg directory: +required "The server directory to create.")
+        )
+        (@subcommand store =>
+            (about: "Manages a content-addressed jar store shared by server directories.")
+            (@setting SubcommandRequiredElseHelp)
+            (@arg store: -s --store +takes_value +required "The store directory.")
+            (@subcommand list =>
+                (about: "Lists the jars in the store.")
+                (@arg json: --json "Prints the jars as JSON.")
+            )
+            (@subcommand gc =>
+                (about: "Removes jars which are neither among the newest builds of their version nor pinned by a lockfile.")
+                (@arg keep: -k --keep +takes_value "The number of newest builds kept per version. Default: 3")
+                (@arg dry_run: --("dry-run") "Only prints what would be removed.")
+                (@arg json: --json "Prints the result as JSON.")
+                (@arg servers: +multiple "Server directories or patterns whose paper-lock.json jars are kept.")
+            )
+        )
+        (@subcommand verify =>
+            (about: "Verifies a jar against the API, through its provenance sidecar or its hash.")
+            (@arg project: -p --project +takes_value "The project to look the hash up in without a sidecar. Default: paper")
+            (@arg database: -D --database +takes_value "Looks the hash up in every project of a local index, see index-sync.")
+            (@arg json: --json "Prints the result as JSON.")
+            (@arg jar: +required "The jar to verify.")
+        )
+        (@subcommand identify =>
+            (about: "Identifies the build of a jar by its sha256 and whether its version is still supported.")
+            (@arg project: -p --project +takes_value "The project the jar belongs to. Default: paper")
+            (@arg database: -D --database +takes_value "Looks the hash up in every project of a local index, see index-sync.")
+            (@arg eol_days: --("eol-days") +takes_value "Days without builds after which a version is unsupported. Default: 90")
+            (@arg superseded: --superseded "Also flags versions with a newer release as unsupported.")
+            (@arg json: --json "Prints the result as JSON.")
+            (@arg jar: +required "The jar to identify.")
+        )
+    ).subcommand(clap_app!(("serve-mock") =>
+        (about: "Serves a local mock of the paper API from a JSON or TOML fixture.")
+        (@arg fixture: -f --fixture +takes_value +required "The JSON or TOML (.toml) fixture of projects, versions, builds and downloads.")
+        (@arg port: --port +takes_value "The port to listen on. Default: 8080")
+        (@arg latency: --latency +takes_value "Latency added to every response, in milliseconds.")
+        (@arg fail_every: --("fail-every") +takes_value "Fails every nth request.")
+        (@arg error_status: --("error-status") +takes_value "The status of injected failures. Default: 500")
+        (@arg truncate: --truncate "Truncates download bodies.")
+    )).subcommand(clap_app!(("mirror-serve") =>
+        (about: "Serves a caching mirror of the paper API, fetching misses from the upstream url.")
+        (@arg store: -s --store +takes_value +required "The directory responses and jars are stored in.")
+        (@arg port: --port +takes_value "The port to listen on. Default: 8080")
+        (@arg bind: --bind +takes_value "The address to listen on. Default: 127.0.0.1")
+        (@arg ttl: --ttl +takes_value "Seconds project and version listings are cached for. Default: 300")
+    )).subcommand(clap_app!(("mirror-sync") =>
+        (about: "Replicates a project's responses and downloads into a static directory tree.")
+        (@arg project: -p --project +takes_value +required "The project to replicate.")
+        (@arg versions: -v --versions +takes_value "The versions to replicate, e.g. 1.16.x. Default: *")
+        (@arg out: -o --out +takes_value +required "The directory to write the mirror to.")
+    )).subcommand(clap_app!(("index-sync") =>
+        (about: "Syncs projects, versions, builds, changes and download hashes into a local SQLite index.")
+        (@arg database: -D --database +takes_value "The index database. Default: paper-index.sqlite")
+        (@arg project: -p --project +takes_value +multiple number_of_values(1) "A project to sync. May be repeated. Default: all projects")
+    )).subcommand(clap_app!(query =>
+        (about: "Runs SQL against the local index, e.g. SELECT * FROM downloads WHERE sha256 = '...'.")
+        (@arg database: -D --database +takes_value "The index database. Default: paper-index.sqlite")
+        (@arg json: --json "Prints the rows as JSON.")
+        (@arg sql: +required "The SQL statement to run.")
+    )).subcommand(clap_app!(("fleet-report") =>
+        (about: "Reports the installed build, builds behind, age and support of many server directories.")
+        (@arg project: -p --project +takes_value +multiple number_of_values(1) "A project jars are looked up in. May be repeated. Default: paper")
+        (@arg eol_days: --("eol-days") +takes_value "Days without builds after which a version is unsupported. Default: 90")
+        (@arg superseded: --superseded "Also flags versions with a newer release as unsupported.")
+        (@arg json: --json "Prints the report as JSON.")
+        (@arg directories: +required +multiple "Server directories or patterns such as 'servers/*'.")
+    )).subcommand(clap_app!(("search-changes") =>
+        (about: "Searches the changes of a version group or version, oldest build first.")
+        (@arg project: -p --project +takes_value +required "The project to search.")
+        (@group vtype +required =>
+            (@arg group: -g --group +takes_value "Defines a version group.")
+            (@arg version: -v --version +takes_value "Defines a version.")
+        )
+        (@arg regex: -r --regex "Treats the query as a regular expression instead of keywords.")
+        (@arg since: --since +takes_value "Only searches builds from this date or time on, e.g. 2021-06-01.")
+        (@arg until: --until +takes_value "Only searches builds up to this date or time.")
+        (@arg json: --json "Prints the matches as JSON.")
+        (@arg query: +required "The keywords or regular expression to search for.")
+    )).get_matches();
+
+    if let Some(url) = app_matcher.value_of("url") {
+        paper_api::set_base_url(url)?;
+    }
 
     if app_matcher.is_present("debug") {
         handle_matches::<PaperClientDebug>(app_matcher).await?;
@@ -51,7 +245,7 @@ async fn main() -> paper_api::Result<()> {
 }
 
 async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Result<()>
-    where ClientConfig: PaperClientConfig + Send
+    where ClientConfig: PaperClientConfig + Send + 'static
 {
     match matcher.subcommand_name() {
         Some("projects") => {
@@ -74,40 +268,67 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
             let version_str = download_command.value_of("version").unwrap_or("latest");
             let build_str = download_command.value_of("build").unwrap_or("latest");
 
-            let version = if version_str.eq("latest") {
-                let project_info = ClientConfig::get_project(project).await?;
-                project_info.versions[project_info.versions.len() - 1].to_owned()
-            } else {
-                String::from(version_str)
-            };
-            let build = if build_str.eq("latest") {
-                let version_info = ClientConfig::get_version_info(project, &version).await?;
-                version_info.builds[version_info.builds.len() - 1]
+            let (version, build) = if let Some(as_of) = download_command.value_of("as_of") {
+                let at = parse_instant(as_of)?;
+                let spec = version_str.parse::<VersionSpec>()?;
+                match resolve_at::<ClientConfig>(project, &spec, at).await? {
+                    Some(info) => {
+                        println!("Resolved {} {} build {} ({}) as of {}.", project, info.version, info.build, info.time, at.to_rfc3339());
+                        (info.version, info.build)
+                    }
+                    None => {
+                        println!("No build of {} {} was released by {}.", project, spec, at.to_rfc3339());
+                        return Ok(());
+                    }
+                }
             } else {
-                if let Ok(b_i32) = build_str.parse::<i32>() {
-                    b_i32
+                let version = if version_str.eq("latest") {
+                    let project_info = ClientConfig::get_project(project).await?;
+                    project_info.versions[project_info.versions.len() - 1].to_owned()
                 } else {
-                    println!("Build must be defined as a number or \"latest\".");
-                    return Ok(());
-                }
+                    String::from(version_str)
+                };
+                let build = if build_str.eq("latest") {
+                    let version_info = ClientConfig::get_version_info(project, &version).await?;
+                    version_info.builds[version_info.builds.len() - 1]
+                } else {
+                    if let Ok(b_i32) = build_str.parse::<i32>() {
+                        b_i32
+                    } else {
+                        println!("Build must be defined as a number or \"latest\".");
+                        return Ok(());
+                    }
+                };
+                (version, build)
             };
+            let java = download_command.value_of("java").unwrap_or("java");
+            if let Some(warning) = enforce_java(java, project, &version, download_command.is_present("ignore_java")).await? {
+                println!("Warning: {}", warning);
+            }
             let download_info = ClientConfig::get_version_builds(project, &version, build).await?;
-            let download = download_info.downloads.application.name;
+            let download = download_info.downloads.application.name.clone();
+            validate_download_name(&download)?;
 
             let path_buf = Path::new(path);
 
             let file_path = if path_buf.is_dir() {
-                path_buf.join(&Path::new(&*download))
+                path_buf.join(Path::new(&*download))
             } else {
                 path_buf.to_path_buf()
             };
 
-            let mut file = std::fs::File::create(&file_path)?;
-            BuildDownloadRequest::new(project, &version, build, &*download).call::<ClientConfig, _>(move |bytes|
-                file.write_all(bytes).expect("Could not write bytes to file.")
-            ).await?;
+            let create_dirs = download_command.is_present("create_dirs");
+            let provenance = download_command.is_present("provenance");
+            if let Some(store) = download_command.value_of("store") {
+                let link_mode = parse_arg::<LinkMode>(download_command, "link")?.unwrap_or_default();
+                let store = JarStore::new(store).with_link_mode(link_mode);
+                let (_, method) = store.install::<ClientConfig, _>(&download_info, &file_path, create_dirs, provenance).await?;
+                println!("Installed {} to {} from {} ({})", download, file_path.to_str().unwrap(), store.root().display(), method);
+            } else {
+                download_build::<ClientConfig, _>(&download_info, &file_path, create_dirs, provenance).await?;
 
-            println!("Downloaded {} to {}", download, file_path.to_str().unwrap());
+                println!("Downloaded {} to {}", download, file_path.to_str().unwrap());
+            }
         }
         Some("project") => {
             let project_command = matcher.subcommand_matches("project")
@@ -116,6 +337,7 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
             let project = project_command.value_of("project").expect("Arg project required.");
             let version = project_command.value_of("version");
             let group = project_command.value_of("group");
+            let policy = support_policy(project_command)?;
 
             match project_command.subcommand_name() {
                 Some("builds") => {
@@ -152,6 +374,9 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
                                     println!("Project Name: \t{}", info.project_name);
                                     println!("Version:      \t{}", info.version);
                                     println!("Builds:       \t{:?}", info.builds);
+                                    if let Some(java) = required_java(&info) {
+                                        println!("Java:         \t{}", java);
+                                    }
                                 }
                                 Err(e) => {
                                     println!("Error: {}", e);
@@ -214,6 +439,17 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
                                 println!("Project Name: \t{}", info.project_name);
                                 println!("Version:      \t{}", info.version);
                                 println!("Builds:       \t{:?}", info.builds);
+                                match version_support::<ClientConfig>(project, v, &policy).await {
+                                    Ok(support) => {
+                                        println!("Support:      \t{}", support.marker());
+                                        if let Some(warning) = support.warning() {
+                                            println!("Warning: {}", warning);
+                                        }
+                                    }
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                    }
+                                }
                             }
                             Err(e) => {
                                 println!("Error: {}", e);
@@ -240,6 +476,18 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
                                 println!("Project Name:           \t{}", project_info.project_name);
                                 println!("Project Version Groups: \t{:?}", project_info.version_groups);
                                 println!("Project Versions:       \t{:?}", project_info.versions);
+                                match project_support::<ClientConfig>(project, &policy).await {
+                                    Ok(support) => {
+                                        let unsupported: Vec<String> = support.into_iter()
+                                            .filter(|support| !support.is_supported())
+                                            .map(|support| support.version)
+                                            .collect();
+                                        println!("Unsupported Versions:   \t{:?}", unsupported);
+                                    }
+                                    Err(e) => {
+                                        println!("Error: {}", e);
+                                    }
+                                }
                             }
                             Err(e) => {
                                 println!("Error: {}", e);
@@ -250,17 +498,646 @@ async fn handle_matches<ClientConfig>(matcher: ArgMatches<'_>) -> paper_api::Res
                 _ => unreachable!()
             }
         }
+        Some("matrix") => {
+            let matrix_command = matcher.subcommand_matches("matrix")
+                .expect("Sub command must be \"matrix\".");
+
+            let projects: Vec<String> = matrix_command.values_of("project").expect("Arg project required.")
+                .map(String::from)
+                .collect();
+            let matrix = compatibility_matrix::<ClientConfig>(&projects).await?;
+            if matrix_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&matrix)?);
+            } else {
+                print_matrix(&matrix);
+            }
+        }
+        Some("fleet-report") => {
+            let fleet_command = matcher.subcommand_matches("fleet-report")
+                .expect("Sub command must be \"fleet-report\".");
+
+            let projects: Vec<String> = fleet_command.values_of("project")
+                .map(|projects| projects.map(String::from).collect())
+                .unwrap_or_else(|| vec![String::from("paper")]);
+            let mut directories = Vec::new();
+            for pattern in fleet_command.values_of("directories").expect("Arg directories required.") {
+                directories.extend(expand_pattern(pattern)?.into_iter().filter(|path| path.is_dir()));
+            }
+            let policy = support_policy(fleet_command)?;
+            let report = fleet_report::<ClientConfig>(&directories, &projects, &policy).await?;
+            if fleet_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_fleet(&report);
+            }
+        }
+        Some("apply") => {
+            let apply_command = matcher.subcommand_matches("apply")
+                .expect("Sub command must be \"apply\".");
+
+            let config = FleetConfig::from_path(apply_command.value_of("config").unwrap_or("fleet.json"))?;
+            let plans = plan_fleet::<ClientConfig>(&config, chrono::Utc::now()).await?;
+            let outcomes: Vec<ServerOutcome> = if apply_command.is_present("dry_run") {
+                plans.into_iter()
+                    .map(|plan| ServerOutcome { server: plan.server, action: plan.action, backup: None, error: None })
+                    .collect()
+            } else {
+                apply_fleet::<ClientConfig>(&config, &plans).await?
+            };
+            if apply_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&outcomes)?);
+            } else {
+                for outcome in &outcomes {
+                    let action = match &outcome.action {
+                        PlannedAction::UpToDate => String::from("up to date"),
+                        PlannedAction::Update { version, build, .. } => format!("update to {} build {}", version, build),
+                        PlannedAction::Skipped { reason } => format!("skipped: {}", reason),
+                    };
+                    match &outcome.error {
+                        Some(error) => println!("{}\t{}\tfailed: {}", outcome.server, action, error),
+                        None => println!("{}\t{}", outcome.server, action),
+                    }
+                }
+            }
+            if outcomes.iter().any(|outcome| outcome.error.is_some()) {
+                return Err(Box::from("Some servers failed to update."));
+            }
+        }
+        Some("rollback") => {
+            let rollback_command = matcher.subcommand_matches("rollback")
+                .expect("Sub command must be \"rollback\".");
+
+            let config = FleetConfig::from_path(rollback_command.value_of("config").unwrap_or("fleet.json"))?;
+            let server = rollback_command.value_of("server").expect("Arg server required.");
+            let backup = rollback_server::<ClientConfig>(&config, server).await?;
+            match &backup.installed {
+                Some(installed) => println!("Restored {} {} build {} of {} from {}.", installed.project, installed.version, installed.build, server, backup.created),
+                None => println!("Restored {} of {} from {}.", backup.jar, server, backup.created),
+            }
+            println!("{} is held until `release {}`.", server, server);
+        }
+        Some("release") => {
+            let release_command = matcher.subcommand_matches("release")
+                .expect("Sub command must be \"release\".");
+
+            let config = FleetConfig::from_path(release_command.value_of("config").unwrap_or("fleet.json"))?;
+            let server = release_command.value_of("server").expect("Arg server required.");
+            match release_server(&config, server)? {
+                Some(hold) => println!("Released {}, held since {}.", server, hold.created),
+                None => println!("{} was not held.", server),
+            }
+        }
+        Some("init") => {
+            let init_command = matcher.subcommand_matches("init")
+                .expect("Sub command must be \"init\".");
+
+            let max_memory = init_command.value_of("memory").unwrap_or("2G");
+            let store = match init_command.value_of("store") {
+                Some(store) => Some(JarStore::new(store).with_link_mode(parse_arg::<LinkMode>(init_command, "link")?.unwrap_or_default())),
+                None => None,
+            };
+            let options = InitOptions {
+                project: init_command.value_of("project").unwrap_or("paper").to_string(),
+                versions: init_command.value_of("version").unwrap_or("latest").parse()?,
+                channel: if init_command.is_present("experimental") { BuildChannel::Experimental } else { BuildChannel::Default },
+                jar: init_command.value_of("jar").unwrap_or("server.jar").to_string(),
+                min_memory: init_command.value_of("min_memory").unwrap_or(max_memory).to_string(),
+                max_memory: max_memory.to_string(),
+                gc_preset: parse_arg::<GcPreset>(init_command, "gc")?.unwrap_or_default(),
+                jvm_flags: init_command.values_of("jvm_flag").into_iter().flatten().map(String::from).collect(),
+                java: init_command.value_of("java").unwrap_or("java").to_string(),
+                ignore_java: init_command.is_present("ignore_java"),
+                accept_eula: init_command.is_present("accept_eula"),
+                store,
+                force: init_command.is_present("force"),
+                user: init_command.value_of("user").map(String::from).or_else(default_user),
+                group: init_command.value_of("group").map(String::from),
+            };
+            let directory = init_command.value_of("directory").expect("Arg directory required.");
+            let report = init_server::<ClientConfig, _>(directory, &options).await?;
+            println!("Installed {} {} build {} in {}.", report.provenance.project, report.provenance.version, report.provenance.build, report.directory.display());
+            for file in &report.files {
+                println!("\t{}", file.display());
+            }
+            for warning in &report.warnings {
+                println!("Warning: {}", warning);
+            }
+            if !report.eula_accepted {
+                println!("The EULA was not accepted, the server will not start until eula.txt says eula=true (see --accept-eula).");
+            }
+        }
+        Some("store") => {
+            let store_command = matcher.subcommand_matches("store")
+                .expect("Sub command must be \"store\".");
+
+            let store = JarStore::new(store_command.value_of("store").expect("Arg store required."));
+            match store_command.subcommand_name() {
+                Some("list") => {
+                    let list_command = store_command.subcommand_matches("list")
+                        .expect("Sub command must be \"list\".");
+
+                    let entries = store.entries()?;
+                    if list_command.is_present("json") {
+                        println!("{}", serde_json::to_string_pretty(&entries)?);
+                    } else {
+                        print_stored_jars(&entries);
+                    }
+                }
+                Some("gc") => {
+                    let gc_command = store_command.subcommand_matches("gc")
+                        .expect("Sub command must be \"gc\".");
+
+                    let keep = parse_arg::<usize>(gc_command, "keep")?.unwrap_or(3);
+                    let mut directories = Vec::new();
+                    for pattern in gc_command.values_of("servers").into_iter().flatten() {
+                        directories.extend(expand_pattern(pattern)?.into_iter().filter(|path| path.is_dir()));
+                    }
+                    let dry_run = gc_command.is_present("dry_run");
+                    let report = store.gc(keep, &locked_hashes(&directories)?, dry_run)?;
+                    if gc_command.is_present("json") {
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                    } else {
+                        print_gc(&report, dry_run);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some("verify") => {
+            let verify_command = matcher.subcommand_matches("verify")
+                .expect("Sub command must be \"verify\".");
+
+            let project = verify_command.value_of("project").unwrap_or("paper");
+            let jar = verify_command.value_of("jar").expect("Arg jar required.");
+            let verification = match verify_command.value_of("database") {
+                Some(database) => verify_jar_indexed::<ClientConfig, _>(&open_index(database)?, jar).await?,
+                None => verify_jar::<ClientConfig, _>(project, jar).await?,
+            };
+            if verify_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&verification)?);
+            } else {
+                if let (Some(project), Some(version), Some(build)) = (&verification.project, &verification.version, verification.build) {
+                    println!("Build:   \t{} {} build {}", project, version, build);
+                }
+                println!("SHA256:  \t{}", verification.sha256);
+                if let Some(expected) = &verification.expected_sha256 {
+                    println!("Expected:\t{}", expected);
+                }
+                let source = match verification.source {
+                    Some(VerificationSource::Provenance) => "provenance sidecar",
+                    Some(VerificationSource::HashLookup) => "hash lookup",
+                    None => "none",
+                };
+                println!("Source:  \t{}", source);
+            }
+            if !verification.verified {
+                return Err(Box::from(verification.reason.unwrap_or_else(|| String::from("Verification failed."))));
+            }
+            if !verify_command.is_present("json") {
+                println!("Verified.");
+            }
+        }
+        Some("identify") => {
+            let identify_command = matcher.subcommand_matches("identify")
+                .expect("Sub command must be \"identify\".");
+
+            let project = identify_command.value_of("project").unwrap_or("paper");
+            let jar = identify_command.value_of("jar").expect("Arg jar required.");
+            let policy = support_policy(identify_command)?;
+            let identified = match identify_command.value_of("database") {
+                Some(database) => identify_jar_indexed::<ClientConfig, _>(&open_index(database)?, jar).await?,
+                None => identify_jar::<ClientConfig, _>(project, jar).await?,
+            };
+            let identified = match identified {
+                Some(identified) => identified,
+                None => {
+                    match identify_command.value_of("database") {
+                        Some(_) => println!("{} does not match any indexed build.", jar),
+                        None => println!("{} does not match any {} build.", jar, project),
+                    }
+                    return Ok(());
+                }
+            };
+            let support = version_support::<ClientConfig>(&identified.project, &identified.build.version, &policy).await?;
+
+            if identify_command.is_present("json") {
+                let output = serde_json::json!({ "jar": identified, "support": support });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                println!("Project: \t{}", identified.project);
+                println!("Version: \t{} ({})", identified.build.version, support.marker());
+                println!("Build:   \t{}", identified.build.build);
+                println!("Time:    \t{}", identified.build.time);
+                println!("SHA256:  \t{}", identified.sha256);
+                if let Some(provenance) = &identified.provenance {
+                    println!("Source:  \t{} (downloaded {})", provenance.source_url, provenance.downloaded_at);
+                }
+                if let Some(warning) = support.warning() {
+                    println!("Warning: {}", warning);
+                }
+            }
+        }
+        Some("watch") => {
+            let watch_command = matcher.subcommand_matches("watch")
+                .expect("Sub command must be \"watch\".");
+            watch::<ClientConfig>(watch_command).await?;
+        }
+        Some("feed") => {
+            let feed_command = matcher.subcommand_matches("feed")
+                .expect("Sub command must be \"feed\".");
+
+            let sources = feed_command.values_of("source").expect("Arg source required.")
+                .map(|source| match source.find(':') {
+                    Some(split) => Ok((source[..split].to_string(), source[split + 1..].to_string())),
+                    None => Err(format!("Source \"{}\" must be formatted as project:group.", source)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let format = feed_command.value_of("format").unwrap_or("atom").parse::<FeedFormat>()?;
+            let limit = parse_arg::<usize>(feed_command, "limit")?.unwrap_or(50);
+
+            let feed = build_feed::<ClientConfig>(&sources, format, limit).await?;
+            match feed_command.value_of("out") {
+                Some(out) => std::fs::write(out, feed)?,
+                None => print!("{}", feed),
+            }
+        }
+        Some("notes") => {
+            let notes_command = matcher.subcommand_matches("notes")
+                .expect("Sub command must be \"notes\".");
+
+            let project = notes_command.value_of("project").expect("Arg project required.");
+            let since = parse_arg::<i32>(notes_command, "since")?;
+            let (project_name, builds, title) = if let Some(g) = notes_command.value_of("group") {
+                let info = ClientConfig::get_group_builds(project, g).await?;
+                let title = format!("{} {}", info.project_name, info.version_group);
+                (info.project_name, info.builds, title)
+            } else {
+                let v = notes_command.value_of("version").expect("Arg group or version required.");
+                let project_info = ClientConfig::get_project(project).await?;
+                let builds = ClientConfig::get_builds_of_version(project, v).await?;
+                let title = format!("{} {}", project_info.project_name, v);
+                (project_info.project_name, builds, title)
+            };
+            let builds: Vec<BuildInfo> = builds.into_iter()
+                .filter(|build| since.map_or(true, |since| build.build > since))
+                .collect();
+
+            let options = NotesOptions {
+                format: notes_command.value_of("format").unwrap_or("markdown").parse::<NotesFormat>()?,
+                repository: Some(notes_command.value_of("repo").map(String::from)
+                    .unwrap_or_else(|| format!("https://github.com/PaperMC/{}", project_name))),
+                title: Some(title),
+            };
+            let notes = render_notes(&builds, &options);
+            match notes_command.value_of("out") {
+                Some(out) => std::fs::write(out, notes)?,
+                None => print!("{}", notes),
+            }
+        }
+        Some("stats") => {
+            let stats_command = matcher.subcommand_matches("stats")
+                .expect("Sub command must be \"stats\".");
+
+            let project = stats_command.value_of("project").expect("Arg project required.");
+            let top = parse_arg::<usize>(stats_command, "top")?.unwrap_or(5);
+            let report = if let Some(g) = stats_command.value_of("group") {
+                group_stats::<ClientConfig>(project, g, top).await?
+            } else {
+                let v = stats_command.value_of("version").expect("Arg group or version required.");
+                version_stats::<ClientConfig>(project, v, top).await?
+            };
+
+            if stats_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_stats(&report);
+            }
+        }
+        Some("search-changes") => {
+            let search_command = matcher.subcommand_matches("search-changes")
+                .expect("Sub command must be \"search-changes\".");
+
+            let project = search_command.value_of("project").expect("Arg project required.");
+            let query = search_command.value_of("query").expect("Arg query required.");
+            let pattern = if search_command.is_present("regex") {
+                SearchPattern::regex(query)?
+            } else {
+                SearchPattern::keywords(query)
+            };
+            let mut search = SearchQuery::new(pattern);
+            search.since = search_command.value_of("since").map(parse_instant).transpose()?;
+            search.until = search_command.value_of("until").map(parse_instant).transpose()?;
+
+            let matches = if let Some(g) = search_command.value_of("group") {
+                search_group_changes::<ClientConfig>(project, g, &search).await?
+            } else {
+                let v = search_command.value_of("version").expect("Arg group or version required.");
+                search_version_changes::<ClientConfig>(project, v, &search).await?
+            };
+
+            if search_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&matches)?);
+            } else if let Some(first) = matches.first() {
+                for change in &matches {
+                    println!("{}\t{}\t{}\t{}\t{}\t{}\t{}", change.version, change.build, change.time, change.commit,
+                        change.kind, format_references(&change.references), change.summary);
+                }
+                println!("First contained in {} {} build {} ({}).", first.project, first.version, first.build, first.time);
+            } else {
+                println!("No matching changes.");
+            }
+        }
+        Some("serve-mock") => {
+            let serve_command = matcher.subcommand_matches("serve-mock")
+                .expect("Sub command must be \"serve-mock\".");
+            serve_mock(serve_command).await?;
+        }
+        Some("mirror-serve") => {
+            let mirror_command = matcher.subcommand_matches("mirror-serve")
+                .expect("Sub command must be \"mirror-serve\".");
+            mirror_serve::<ClientConfig>(mirror_command).await?;
+        }
+        Some("index-sync") => {
+            let sync_command = matcher.subcommand_matches("index-sync")
+                .expect("Sub command must be \"index-sync\".");
+
+            let projects: Option<Vec<String>> = sync_command.values_of("project")
+                .map(|projects| projects.map(String::from).collect());
+            let mut index = BuildIndex::open(sync_command.value_of("database").unwrap_or("paper-index.sqlite"))?;
+            let report = index.sync::<ClientConfig>(projects.as_deref()).await?;
+            println!("Projects:         \t{}", report.projects);
+            println!("Versions checked: \t{}", report.versions_checked);
+            println!("Versions updated: \t{}", report.versions_updated);
+            println!("Builds added:     \t{}", report.builds_added);
+            println!("Builds removed:   \t{}", report.builds_removed);
+        }
+        Some("query") => {
+            let query_command = matcher.subcommand_matches("query")
+                .expect("Sub command must be \"query\".");
+
+            let database = query_command.value_of("database").unwrap_or("paper-index.sqlite");
+            if !Path::new(database).exists() {
+                println!("No index at {}, run index-sync first.", database);
+                return Ok(());
+            }
+            let index = BuildIndex::open_read_only(database)?;
+            let result = index.query(query_command.value_of("sql").expect("Arg sql required."))?;
+            if query_command.is_present("json") {
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                println!("{}", result.columns.join("\t"));
+                for row in &result.rows {
+                    let values: Vec<String> = row.iter().map(|value| match value {
+                        serde_json::Value::String(text) => text.clone(),
+                        value => value.to_string(),
+                    }).collect();
+                    println!("{}", values.join("\t"));
+                }
+            }
+        }
+        Some("mirror-sync") => {
+            let sync_command = matcher.subcommand_matches("mirror-sync")
+                .expect("Sub command must be \"mirror-sync\".");
+
+            let project = sync_command.value_of("project").expect("Arg project required.");
+            let versions = sync_command.value_of("versions").unwrap_or("*").parse::<VersionSpec>()?;
+            let out = Path::new(sync_command.value_of("out").expect("Arg out required."));
+
+            let report = mirror_sync::<ClientConfig>(project, &versions, out).await?;
+            println!("Responses written: \t{}", report.responses);
+            println!("Builds fetched:    \t{}", report.builds_fetched);
+            println!("Builds unchanged:  \t{}", report.builds_skipped);
+            println!("Downloaded:        \t{}", report.downloaded);
+            println!("Verified:          \t{}", report.verified);
+            println!("Repaired:          \t{}", report.repaired);
+        }
         _ => unreachable!()
     };
     Ok(())
 }
 
+fn parse_arg<T>(matches: &ArgMatches<'_>, name: &str) -> paper_api::Result<Option<T>>
+    where T: std::str::FromStr
+{
+    match matches.value_of(name) {
+        Some(value) => value.parse::<T>().map(Some)
+            .map_err(|_| Box::from(format!("Invalid value \"{}\" for {}.", value, name))),
+        None => Ok(None),
+    }
+}
+
+async fn serve_mock(matches: &ArgMatches<'_>) -> paper_api::Result<()> {
+    let fixture = MockFixture::from_path(matches.value_of("fixture").expect("Arg fixture required."))?;
+    let port = parse_arg::<u16>(matches, "port")?.unwrap_or(8080);
+    let options = MockOptions {
+        latency: Duration::from_millis(parse_arg::<u64>(matches, "latency")?.unwrap_or(0)),
+        fail_every: parse_arg::<u64>(matches, "fail_every")?,
+        error_status: parse_arg::<u16>(matches, "error_status")?.unwrap_or(500),
+        truncate_downloads: matches.is_present("truncate"),
+    };
+
+    let server = MockServer::start(fixture, options, ([127, 0, 0, 1], port).into()).await?;
+    println!("Serving mock API at {}", server.url());
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
 fn print_changes(info: &Vec<ChangesInfo>) {
     println!("Changes:");
     for change_info in info {
         println!("\tCommit: {}", change_info.commit);
         println!("\tMessage: \n`\n{}\n`", change_info.message);
         println!("\tSummary: {}", change_info.summary);
+        let classified = classify(change_info);
+        println!("\tKind: {}", classified.kind);
+        println!("\tReferences: {}", format_references(&classified.references));
+        if !classified.upstream_commits.is_empty() {
+            println!("\tUpstream commits:");
+            for commit in &classified.upstream_commits {
+                println!("\t\t{}\t{}\t{}", commit.project, commit.hash, commit.summary);
+            }
+        }
         println!();
     }
-}
\ No newline at end of file
+}
+
+fn format_hours(hours: f64) -> String {
+    if hours >= 48.0 {
+        format!("{:.1}d", hours / 24.0)
+    } else {
+        format!("{:.1}h", hours)
+    }
+}
+
+fn print_stats(report: &StatsReport) {
+    println!("Version\tBuilds\tBuilds/week\tMedian gap\tChanges/build\tLast build\tLast build age");
+    for version in &report.versions {
+        println!("{}\t{}\t{:.2}\t{}\t{:.2}\t{}\t{}", version.version, version.builds, version.builds_per_week,
+                 version.median_gap_hours.map(format_hours).unwrap_or_else(|| String::from("-")),
+                 version.changes_per_build, version.last_build, format_hours(version.last_build_age_hours));
+    }
+    println!();
+    println!("Most changed weeks:");
+    for period in &report.most_changed_periods {
+        println!("\t{}\t{} builds\t{} changes", period.week_start, period.builds, period.changes);
+    }
+}
+
+fn print_matrix(matrix: &CompatibilityMatrix) {
+    let names: Vec<&str> = matrix.projects.iter().map(|project| project.project_id.as_str()).collect();
+    println!("Version\tGroup\t{}", names.join("\t"));
+    for row in &matrix.rows {
+        let cells: Vec<String> = row.cells.iter().zip(row.group_supported.iter()).map(|(cell, group_supported)| match cell {
+            Some(latest) => format!("#{} ({})", latest.build, format_hours(latest.age_hours)),
+            None if *group_supported => String::from("group"),
+            None => String::from("-"),
+        }).collect();
+        println!("{}\t{}\t{}", row.version, row.group.as_deref().unwrap_or("-"), cells.join("\t"));
+    }
+}
+
+fn print_stored_jars(entries: &[StoredJar]) {
+    println!("SHA256\tProject\tVersion\tBuild\tSize");
+    for entry in entries {
+        match &entry.provenance {
+            Some(provenance) => println!("{}\t{}\t{}\t{}\t{}", entry.sha256, provenance.project, provenance.version, provenance.build, entry.size),
+            None => println!("{}\t-\t-\t-\t{}", entry.sha256, entry.size),
+        }
+    }
+}
+
+fn print_gc(report: &GcReport, dry_run: bool) {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for entry in &report.removed {
+        match &entry.provenance {
+            Some(provenance) => println!("{} {} {} build {} ({})", verb, provenance.project, provenance.version, provenance.build, entry.sha256),
+            None => println!("{} {}", verb, entry.sha256),
+        }
+    }
+    println!("{} {} jars ({} bytes), kept {}.", verb, report.removed.len(), report.freed_bytes, report.kept.len());
+}
+
+fn print_fleet(report: &[ServerStatus]) {
+    println!("Directory\tProject\tVersion\tBuild\tLatest\tBehind\tAge\tSupport");
+    for server in report {
+        match (&server.installed, &server.error) {
+            (Some(installed), _) => println!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                server.directory, installed.project, installed.version, installed.build,
+                server.latest_build.map(|build| build.to_string()).unwrap_or_else(|| String::from("-")),
+                server.builds_behind,
+                server.age_days.map(|days| format!("{}d", days)).unwrap_or_else(|| String::from("-")),
+                server.support.as_ref().map(|support| support.marker()).unwrap_or("-")),
+            (None, error) => println!("{}\t-\t-\t-\t-\t-\t-\t{}", server.directory, error.as_deref().unwrap_or("unknown")),
+        }
+    }
+}
+
+fn open_index(database: &str) -> paper_api::Result<BuildIndex> {
+    if !Path::new(database).exists() {
+        return Err(Box::from(format!("No index at {}, run index-sync first.", database)));
+    }
+    BuildIndex::open_read_only(database)
+}
+
+fn format_references(references: &[u32]) -> String {
+    if references.is_empty() {
+        return String::from("-");
+    }
+    references.iter().map(|number| format!("#{}", number)).collect::<Vec<_>>().join(",")
+}
+
+async fn mirror_serve<ClientConfig>(matches: &ArgMatches<'_>) -> paper_api::Result<()>
+    where ClientConfig: PaperClientConfig + Send + 'static
+{
+    let store = MirrorStore::new(matches.value_of("store").expect("Arg store required."));
+    let bind = parse_arg::<IpAddr>(matches, "bind")?.unwrap_or_else(|| IpAddr::from([127, 0, 0, 1]));
+    let port = parse_arg::<u16>(matches, "port")?.unwrap_or(8080);
+    let options = MirrorOptions {
+        ttl: Duration::from_secs(parse_arg::<u64>(matches, "ttl")?.unwrap_or(300)),
+    };
+
+    let server = MirrorServer::start::<ClientConfig>(store, options, (bind, port).into()).await?;
+    println!("Serving mirror at {}", server.url());
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+fn support_policy(matches: &ArgMatches<'_>) -> paper_api::Result<SupportPolicy> {
+    let mut policy = SupportPolicy::default();
+    if let Some(days) = parse_arg::<i64>(matches, "eol_days")? {
+        policy.max_build_age = chrono::Duration::days(days);
+    }
+    policy.superseded = matches.is_present("superseded");
+    Ok(policy)
+}
+
+fn parse_duration(duration: &str) -> paper_api::Result<Duration> {
+    let duration = duration.trim();
+    let split = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (amount, unit) = duration.split_at(split);
+    let amount = amount.parse::<u64>()
+        .map_err(|_| format!("Invalid duration \"{}\".", duration))?;
+    let seconds = match unit {
+        "" | "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 60 * 60,
+        "d" => amount * 60 * 60 * 24,
+        "w" => amount * 60 * 60 * 24 * 7,
+        _ => return Err(Box::from(format!("Invalid duration unit \"{}\".", unit))),
+    };
+    Ok(Duration::from_secs(seconds))
+}
+
+async fn watch<ClientConfig>(matches: &ArgMatches<'_>) -> paper_api::Result<()>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project = matches.value_of("project").expect("Arg project required.");
+    let spec = matches.value_of("version").expect("Arg version required.").parse::<VersionSpec>()?;
+    let interval = parse_duration(matches.value_of("interval").unwrap_or("10m"))?;
+    let state_path = matches.value_of("state").unwrap_or("paper-watch.json");
+    let mut watch = BuildWatch::new(project, spec, interval).cursor(WatchState::load(state_path)?);
+
+    loop {
+        if let Err(e) = poll_builds::<ClientConfig>(matches, &mut watch, state_path).await {
+            println!("Error: {}", e);
+        }
+        if matches.is_present("once") {
+            return Ok(());
+        }
+        tokio::time::delay_for(interval).await;
+    }
+}
+
+async fn poll_builds<ClientConfig>(matches: &ArgMatches<'_>, watch: &mut BuildWatch, state_path: &str) -> paper_api::Result<()>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let before = watch.current_cursor().clone();
+    let events = watch.poll::<ClientConfig>().await?;
+    if watch.current_cursor() != &before {
+        watch.current_cursor().save(state_path)?;
+        for (key, build) in &watch.current_cursor().last_builds {
+            if before.last_builds.get(key) != Some(build) {
+                println!("Watching {} from build {}", key, build);
+            }
+        }
+    }
+
+    for event in events {
+        println!("New build: {} {} build {}", event.project, event.version, event.build);
+        if let Some(exec) = matches.value_of("exec") {
+            let status = tokio::process::Command::new(exec).envs(event.env()).status().await?;
+            if !status.success() {
+                return Err(Box::from(format!("Hook exited with {} for build {}.", status, event.build)));
+            }
+        }
+        watch.cursor_mut().set_last_build(&event.project, &event.version, event.build);
+        watch.current_cursor().save(state_path)?;
+        // The hook already ran, a webhook failure must not replay it on the next poll.
+        if let Some(webhook) = matches.value_of("webhook") {
+            if let Err(e) = post_json(webhook, &event).await {
+                println!("Error: webhook failed for {} {} build {}: {}", event.project, event.version, event.build, e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Mutex;
+
+use futures_util::StreamExt;
+use tokio::runtime::{Builder, Runtime};
+
+use super::paper::*;
+use super::{PaperClient, PaperClientConfig, Result};
+
+/// A synchronous client mirroring [`PaperClientConfig`], driving requests on its own runtime.
+///
+/// Its methods panic when called from within a tokio runtime, such as inside an async function,
+/// since a runtime cannot block the thread of another. Use the async client there.
+pub struct BlockingClient<ClientConfig = PaperClient> {
+    runtime: Mutex<Runtime>,
+    config: PhantomData<fn() -> ClientConfig>,
+}
+
+impl<ClientConfig> BlockingClient<ClientConfig>
+    where ClientConfig: PaperClientConfig + Send
+{
+    pub fn new() -> Result<Self> {
+        let runtime = Builder::new().basic_scheduler().enable_all().build()?;
+        Ok(Self { runtime: Mutex::new(runtime), config: PhantomData })
+    }
+
+    fn block_on<F>(&self, future: F) -> F::Output where F: Future {
+        let mut runtime = self.runtime.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        runtime.block_on(future)
+    }
+
+    pub fn get_projects(&self) -> Result<ProjectsResponse> {
+        self.block_on(ClientConfig::get_projects())
+    }
+
+    pub fn get_project<T>(&self, project: T) -> Result<ProjectResponse> where T: Into<String> + Send {
+        self.block_on(ClientConfig::get_project(project))
+    }
+
+    pub fn get_group_info<T>(&self, project: T, group: T) -> Result<ProjectGroupInfoResponse> where T: Into<String> + Send {
+        self.block_on(ClientConfig::get_group_info(project, group))
+    }
+
+    pub fn get_group_builds<T>(&self, project: T, group: T) -> Result<ProjectGroupBuildsResponse> where T: Into<String> + Send {
+        self.block_on(ClientConfig::get_group_builds(project, group))
+    }
+
+    pub fn get_version_info<T>(&self, project: T, version: T) -> Result<ProjectVersionInfoResponse> where T: Into<String> + Send {
+        self.block_on(ClientConfig::get_version_info(project, version))
+    }
+
+    pub fn get_version_builds<T>(&self, project: T, version: T, build: i32) -> Result<ProjectVersionBuildsResponse> where T: Into<String> + Send {
+        self.block_on(ClientConfig::get_version_builds(project, version, build))
+    }
+
+    pub fn download<Function>(&self, request: &BuildDownloadRequest, downloader: Function) -> Result<()>
+        where Function: FnMut(&[u8]) -> Result<()>
+    {
+        self.block_on(request.try_call::<ClientConfig, Function>(downloader))
+    }
+
+    /// Copies the download into a synchronous writer, returning the number of bytes written.
+    pub fn copy_to<W>(&self, request: &BuildDownloadRequest, writer: &mut W) -> Result<u64> where W: Write {
+        self.block_on(async {
+            let mut stream = request.stream::<ClientConfig>().await?;
+            let mut total = 0;
+            while let Some(chunk) = stream.next().await {
+                let bytes = chunk?;
+                writer.write_all(&bytes)?;
+                total += bytes.len() as u64;
+            }
+            writer.flush()?;
+            Ok(total)
+        })
+    }
+
+    pub fn to_path<P>(&self, request: &BuildDownloadRequest, path: P, create_dirs: bool, expected_sha256: Option<&str>) -> Result<String>
+        where P: AsRef<Path>
+    {
+        self.block_on(request.to_path::<ClientConfig, P>(path, create_dirs, expected_sha256))
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use super::paper::{BuildInfo, ChangesInfo};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    UpstreamUpdate,
+    Revert,
+    Security,
+    Performance,
+    Fix,
+    Feature,
+    Other,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChangeKind::UpstreamUpdate => "upstream",
+            ChangeKind::Revert => "revert",
+            ChangeKind::Security => "security",
+            ChangeKind::Performance => "performance",
+            ChangeKind::Fix => "fix",
+            ChangeKind::Feature => "feature",
+            ChangeKind::Other => "other",
+        })
+    }
+}
+
+/// A commit of an upstream project listed in an upstream update, e.g. under `Spigot Changes:`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamCommit {
+    pub project: String,
+    pub hash: String,
+    pub summary: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClassifiedChange {
+    pub commit: String,
+    pub summary: String,
+    pub kind: ChangeKind,
+    /// Issue and pull request numbers referenced as `#1234`.
+    pub references: Vec<u32>,
+    /// The upstream projects named in an upstream update, e.g. `Bukkit`, `CraftBukkit`, `Spigot`.
+    pub upstream_projects: Vec<String>,
+    pub upstream_commits: Vec<UpstreamCommit>,
+}
+
+/// The words of each kind, tried in order. Words only match whole, with the inflections listed.
+static KIND_PATTERNS: Lazy<Vec<(ChangeKind, Regex)>> = Lazy::new(|| {
+    [
+        (ChangeKind::Security, r"security|exploits?|vulnerab\w*|cve-\d+(?:-\d+)?|dupes?"),
+        (ChangeKind::Performance, r"perf|performance|optimi[sz]\w*|faster|speed up|lag(?:s|gy|ging)?|reduce[ds]? allocations?|cach(?:e|es|ed|ing)"),
+        (ChangeKind::Fix, r"fix(?:es|ed|ing)?|bugs?|crash(?:es|ed|ing)?|correct(?:s|ed|ly)?|prevent(?:s|ed|ing)?|resolve[ds]?|don't|do not|properly"),
+        (ChangeKind::Feature, r"add(?:s|ed|ing)?|implement\w*|introduc\w*|support\w*|expos\w*|allow\w*|apis?|options?"),
+    ].iter()
+        .map(|(kind, words)| (*kind, Regex::new(&format!(r"\b(?:{})\b", words)).expect("Change kind pattern must compile.")))
+        .collect()
+});
+static REFERENCE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|[\s(\[])#(\d+)\b").expect("Reference pattern must compile."));
+static UPSTREAM_HEADER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^Updated? Upstream \(([^)]*)\)").expect("Upstream pattern must compile."));
+static SECTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\w+) Changes:\s*$").expect("Section pattern must compile."));
+static UPSTREAM_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([0-9a-fA-F]{7,40})\s+(.*)$").expect("Commit pattern must compile."));
+
+fn kind_of(summary: &str) -> ChangeKind {
+    let lower = summary.to_lowercase();
+    if lower.starts_with("revert") {
+        ChangeKind::Revert
+    } else if lower.starts_with("updated upstream") || lower.starts_with("update upstream") {
+        ChangeKind::UpstreamUpdate
+    } else {
+        KIND_PATTERNS.iter()
+            .find(|(_, pattern)| pattern.is_match(&lower))
+            .map_or(ChangeKind::Other, |(kind, _)| *kind)
+    }
+}
+
+pub fn classify(change: &ChangesInfo) -> ClassifiedChange {
+    let mut upstream_projects: Vec<String> = UPSTREAM_HEADER.captures(change.summary.trim())
+        .map(|captures| captures[1].split('/').map(|project| project.trim().to_string()).filter(|p| !p.is_empty()).collect())
+        .unwrap_or_default();
+
+    // Upstream commit lines reference upstream issues, so only the remaining lines are scanned for references.
+    let mut references = Vec::new();
+    let mut upstream_commits = Vec::new();
+    let mut current: Option<String> = None;
+    for line in change.message.lines().map(str::trim) {
+        if let Some(captures) = SECTION.captures(line) {
+            current = Some(captures[1].to_string());
+        } else if let (Some(project), Some(captures)) = (&current, UPSTREAM_LINE.captures(line)) {
+            upstream_commits.push(UpstreamCommit {
+                project: project.clone(),
+                hash: captures[1].to_string(),
+                summary: captures[2].to_string(),
+            });
+            continue;
+        } else if !line.is_empty() {
+            current = None;
+        }
+        references.extend(REFERENCE.captures_iter(line).filter_map(|captures| captures[1].parse::<u32>().ok()));
+    }
+    references.extend(REFERENCE.captures_iter(&change.summary).filter_map(|captures| captures[1].parse::<u32>().ok()));
+    references.sort_unstable();
+    references.dedup();
+    for commit in &upstream_commits {
+        if !upstream_projects.contains(&commit.project) {
+            upstream_projects.push(commit.project.clone());
+        }
+    }
+
+    ClassifiedChange {
+        commit: change.commit.clone(),
+        summary: change.summary.clone(),
+        kind: kind_of(&change.summary),
+        references,
+        upstream_projects,
+        upstream_commits,
+    }
+}
+
+/// Whether any change of `build` pulls in upstream (e.g. Spigot) changes.
+pub fn includes_upstream(build: &BuildInfo) -> bool {
+    build.changes.iter().any(|change| classify(change).kind == ChangeKind::UpstreamUpdate)
+}
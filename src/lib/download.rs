@@ -0,0 +1,210 @@
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use hyper::{Body, Response};
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::Result;
+
+/// The body of a successful download response, yielded chunk by chunk.
+pub struct DownloadStream {
+    content_length: Option<u64>,
+    body: Body,
+}
+
+impl DownloadStream {
+    pub fn new(response: Response<Body>) -> Self {
+        let content_length = response.headers().get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .or_else(|| HttpBody::size_hint(response.body()).exact());
+        Self { content_length, body: response.into_body() }
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Copies the remaining body into `writer`, returning the number of bytes written.
+    pub async fn copy_to<W>(mut self, writer: &mut W) -> Result<u64> where W: AsyncWrite + Unpin {
+        let mut total = 0;
+        while let Some(chunk) = self.next().await {
+            let bytes = chunk?;
+            writer.write_all(&bytes).await?;
+            total += bytes.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(total)
+    }
+
+    /// Hands every chunk to `sink` in order, waiting for each before reading the next, and prints
+    /// progress when `debug` is set. Returns the number of bytes read.
+    pub async fn for_each_chunk<F, Fut>(mut self, debug: bool, mut sink: F) -> Result<u64>
+        where F: FnMut(Bytes) -> Fut,
+              Fut: Future<Output = Result<()>>
+    {
+        let size = self.content_length.unwrap_or(0);
+        let mut total = 0;
+        while let Some(chunk) = self.next().await {
+            let bytes = chunk?;
+            total += bytes.len() as u64;
+            sink(bytes).await?;
+            if debug {
+                print!("\rDownloaded: ({}/{}){}%", total, size, (total * 100u64) / size.max(1));
+            }
+        }
+        if debug {
+            println!();
+        }
+        Ok(total)
+    }
+}
+
+impl Stream for DownloadStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.body).poll_next(cx).map(|chunk| chunk.map(|result| result.map_err(Box::from)))
+    }
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file which is written to a temporary sibling and only moved over the target once it has
+/// been fully written, synced and (optionally) verified against a sha256 hash.
+pub struct AtomicFile {
+    target: PathBuf,
+    temp: PathBuf,
+    file: Option<File>,
+    hasher: Sha256,
+}
+
+impl AtomicFile {
+    pub fn create<P>(target: P, create_dirs: bool) -> Result<Self> where P: AsRef<Path> {
+        let target = target.as_ref().to_path_buf();
+        let parent = parent_dir(&target).to_path_buf();
+
+        if !parent.is_dir() {
+            if create_dirs {
+                std::fs::create_dir_all(&parent)?;
+            } else {
+                return Err(Box::from(format!("Directory {} does not exist.", parent.display())));
+            }
+        }
+
+        let file_name = target.file_name()
+            .ok_or_else(|| format!("Path {} does not name a file.", target.display()))?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}-{}.part", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let temp = parent.join(temp_name);
+
+        let file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp)?;
+
+        Ok(Self { target, temp, file: Some(file), hasher: Sha256::new() })
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        let file = self.file.as_mut().ok_or("File has already been finished.")?;
+        file.write_all(bytes)?;
+        self.hasher.update(bytes);
+        Ok(())
+    }
+
+    /// Syncs the temporary file, checks it against `expected_sha256` and renames it over the target.
+    pub fn finish(mut self, expected_sha256: Option<&str>) -> Result<String> {
+        let mut file = self.file.take().ok_or("File has already been finished.")?;
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+
+        let actual = format!("{:x}", std::mem::take(&mut self.hasher).finalize());
+        if let Some(expected) = expected_sha256 {
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(Box::from(format!("Hash mismatch for {}: expected {}, got {}.", self.target.display(), expected, actual)));
+            }
+        }
+
+        std::fs::rename(&self.temp, &self.target)?;
+        sync_parent(&self.target)?;
+        Ok(actual)
+    }
+}
+
+/// Writes `bytes` to `path` through an [`AtomicFile`], creating missing parent directories.
+pub fn write_atomic<P>(path: P, bytes: &[u8]) -> Result<()> where P: AsRef<Path> {
+    let mut file = AtomicFile::create(path, true)?;
+    file.write(bytes)?;
+    file.finish(None)?;
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Syncs the directory of `path` so that a rename into it survives a crash.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> Result<()> {
+    File::open(parent_dir(path))?.sync_all()?;
+    Ok(())
+}
+
+/// Directories cannot be opened for syncing on Windows, where renames are journaled instead.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.temp.exists() {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+pub fn sha256_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+pub fn sha256_file<P>(path: P) -> Result<String> where P: AsRef<Path> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The path of a file keyed by its sha256 under `root`, `objects/<xx>/<sha256>` with `xx` the
+/// first two hex digits.
+pub fn object_path(root: &Path, sha256: &str) -> Result<PathBuf> {
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Box::from(format!("Invalid sha256 \"{}\".", sha256)));
+    }
+    let sha256 = sha256.to_ascii_lowercase();
+    Ok(root.join("objects").join(&sha256[..2]).join(sha256))
+}
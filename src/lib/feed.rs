@@ -0,0 +1,156 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+
+use super::paper::{BuildDownloadRequest, BuildInfo, ChangesInfo, ProjectGroupBuildsResponse, ProjectVersionBuildsRequest, Request};
+use super::{PaperClientConfig, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+impl FromStr for FeedFormat {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "atom" => Ok(FeedFormat::Atom),
+            "rss" => Ok(FeedFormat::Rss),
+            _ => Err(Box::from(format!("Unknown feed format \"{}\".", format))),
+        }
+    }
+}
+
+/// One build of a feed, with its links resolved against the client's base url.
+#[derive(Debug, Clone)]
+pub struct FeedEntry {
+    pub project_id: String,
+    pub project_name: String,
+    pub id: String,
+    pub title: String,
+    pub time: DateTime<Utc>,
+    pub download_url: String,
+    pub changes: Vec<ChangesInfo>,
+}
+
+impl FeedEntry {
+    pub fn from_build_info<ClientConfig>(project_id: &str, project_name: &str, info: &BuildInfo) -> Result<Self>
+        where ClientConfig: PaperClientConfig
+    {
+        let application = &info.downloads.application;
+        let build_path = ProjectVersionBuildsRequest::new(project_id, &info.version, info.build).build_request_url();
+        Ok(Self {
+            project_id: project_id.to_string(),
+            project_name: project_name.to_string(),
+            id: super::build_url(&ClientConfig::base_url(), &build_path),
+            title: format!("{} {} build #{}", project_name, info.version, info.build),
+            time: info.parsed_time()?,
+            download_url: BuildDownloadRequest::new(project_id, &info.version, info.build, &application.name)
+                .download_url::<ClientConfig>(),
+            changes: info.changes.clone(),
+        })
+    }
+
+    fn content_html(&self) -> String {
+        if self.changes.is_empty() {
+            return String::from("<p>No changes.</p>");
+        }
+        let mut html = String::from("<ul>");
+        for change in &self.changes {
+            let commit: String = change.commit.chars().take(7).collect();
+            let _ = write!(html, "<li><code>{}</code> {}</li>", escape_xml(&commit), escape_xml(&change.summary));
+        }
+        html.push_str("</ul>");
+        html
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Collects the builds of a version group as feed entries.
+pub fn group_entries<ClientConfig>(response: &ProjectGroupBuildsResponse) -> Result<Vec<FeedEntry>>
+    where ClientConfig: PaperClientConfig
+{
+    response.builds.iter()
+        .map(|info| FeedEntry::from_build_info::<ClientConfig>(&response.project_id, &response.project_name, info))
+        .collect()
+}
+
+/// Renders `entries` newest first, keeping at most `limit` of them.
+pub fn render_feed(title: &str, link: &str, entries: &[FeedEntry], format: FeedFormat, limit: usize) -> String {
+    let mut entries: Vec<&FeedEntry> = entries.iter().collect();
+    entries.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.id.cmp(&b.id)));
+    entries.truncate(limit);
+
+    let updated = entries.first().map(|entry| entry.time).unwrap_or_else(Utc::now);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    match format {
+        FeedFormat::Atom => {
+            xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+            let _ = writeln!(xml, "  <title>{}</title>", escape_xml(title));
+            let _ = writeln!(xml, "  <id>{}</id>", escape_xml(link));
+            let _ = writeln!(xml, "  <link href=\"{}\"/>", escape_xml(link));
+            let _ = writeln!(xml, "  <updated>{}</updated>", updated.to_rfc3339());
+            for entry in entries {
+                xml.push_str("  <entry>\n");
+                let _ = writeln!(xml, "    <title>{}</title>", escape_xml(&entry.title));
+                let _ = writeln!(xml, "    <id>{}</id>", escape_xml(&entry.id));
+                let _ = writeln!(xml, "    <link href=\"{}\"/>", escape_xml(&entry.download_url));
+                let _ = writeln!(xml, "    <updated>{}</updated>", entry.time.to_rfc3339());
+                let _ = writeln!(xml, "    <author><name>{}</name></author>", escape_xml(&entry.project_name));
+                let _ = writeln!(xml, "    <content type=\"html\">{}</content>", escape_xml(&entry.content_html()));
+                xml.push_str("  </entry>\n");
+            }
+            xml.push_str("</feed>\n");
+        }
+        FeedFormat::Rss => {
+            xml.push_str("<rss version=\"2.0\">\n  <channel>\n");
+            let _ = writeln!(xml, "    <title>{}</title>", escape_xml(title));
+            let _ = writeln!(xml, "    <link>{}</link>", escape_xml(link));
+            let _ = writeln!(xml, "    <description>{}</description>", escape_xml(title));
+            let _ = writeln!(xml, "    <lastBuildDate>{}</lastBuildDate>", updated.to_rfc2822());
+            for entry in entries {
+                xml.push_str("    <item>\n");
+                let _ = writeln!(xml, "      <title>{}</title>", escape_xml(&entry.title));
+                let _ = writeln!(xml, "      <guid isPermaLink=\"false\">{}</guid>", escape_xml(&entry.id));
+                let _ = writeln!(xml, "      <link>{}</link>", escape_xml(&entry.download_url));
+                let _ = writeln!(xml, "      <pubDate>{}</pubDate>", entry.time.to_rfc2822());
+                let _ = writeln!(xml, "      <description>{}</description>", escape_xml(&entry.content_html()));
+                xml.push_str("    </item>\n");
+            }
+            xml.push_str("  </channel>\n</rss>\n");
+        }
+    }
+    xml
+}
+
+/// Fetches the builds of each `(project, group)` pair and renders them as a single feed.
+pub async fn build_feed<ClientConfig>(sources: &[(String, String)], format: FeedFormat, limit: usize) -> Result<String>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let mut entries = Vec::new();
+    let mut titles = Vec::new();
+    for (project, group) in sources {
+        let response = ClientConfig::get_group_builds(project.as_str(), group.as_str()).await?;
+        titles.push(format!("{} {}", response.project_name, response.version_group));
+        entries.extend(group_entries::<ClientConfig>(&response)?);
+    }
+    let title = format!("{} builds", titles.join(", "));
+    Ok(render_feed(&title, &ClientConfig::base_url(), &entries, format, limit))
+}
@@ -0,0 +1,224 @@
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use super::download::sha256_file;
+use super::identify::{BuildCatalog, VersionHistory};
+use super::provenance::Provenance;
+use super::support::{SupportPolicy, VersionSupport, assess_version};
+use super::{PaperClientConfig, Result};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifiedBy {
+    Provenance,
+    Hash,
+    VersionHistory,
+}
+
+/// The build installed in a server directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstalledBuild {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    pub identified_by: IdentifiedBy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerStatus {
+    pub directory: String,
+    pub jar: Option<String>,
+    pub installed: Option<InstalledBuild>,
+    pub build_time: Option<String>,
+    pub latest_build: Option<i32>,
+    /// The number of newer builds of the installed version.
+    pub builds_behind: usize,
+    pub age_days: Option<i64>,
+    pub support: Option<VersionSupport>,
+    /// Why the server could not be identified or assessed.
+    pub error: Option<String>,
+}
+
+impl ServerStatus {
+    fn failed(directory: &Path, jar: Option<String>, error: String) -> Self {
+        Self {
+            directory: directory.display().to_string(),
+            jar,
+            installed: None,
+            build_time: None,
+            latest_build: None,
+            builds_behind: 0,
+            age_days: None,
+            support: None,
+            error: Some(error),
+        }
+    }
+}
+
+fn has_wildcard(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+fn wildcard_regex(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+/// Expands `*` and `?` in any component of `pattern`, for shells which leave them alone. Paths
+/// without wildcards are returned as they are.
+pub fn expand_pattern(pattern: &str) -> Result<Vec<PathBuf>> {
+    if !has_wildcard(pattern) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+
+    let mut paths = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy().into_owned(),
+            component => {
+                paths.iter_mut().for_each(|path| path.push(component.as_os_str()));
+                continue;
+            }
+        };
+        if !has_wildcard(&name) {
+            paths.iter_mut().for_each(|path| path.push(&name));
+            continue;
+        }
+
+        let regex = wildcard_regex(&name)?;
+        let mut expanded = Vec::new();
+        for path in &paths {
+            let dir = if path.as_os_str().is_empty() { Path::new(".") } else { path.as_path() };
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            let mut matches: Vec<PathBuf> = entries.filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    !name.starts_with('.') && regex.is_match(&name)
+                })
+                .map(|entry| path.join(entry.file_name()))
+                .collect();
+            matches.sort();
+            expanded.extend(matches);
+        }
+        paths = expanded;
+    }
+    Ok(paths)
+}
+
+/// The jars directly inside a server directory, sorted by name.
+pub fn server_jars(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut jars: Vec<PathBuf> = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("jar")))
+        .collect();
+    jars.sort();
+    Ok(jars)
+}
+
+/// Identifies the build installed in `directory`: by the provenance sidecar of a jar which still
+/// matches it, by the hash of its jars against the builds in `catalog`, and otherwise by its
+/// `version_history.json`, loading the projects of sidecars and histories when needed.
+pub async fn identify_server<ClientConfig>(catalog: &mut BuildCatalog, directory: &Path) -> Result<(Option<PathBuf>, Option<InstalledBuild>)>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let jars = server_jars(directory)?;
+    for jar in &jars {
+        let sha256 = sha256_file(jar)?;
+        if let Some(provenance) = Provenance::read_verified(jar, &sha256)? {
+            catalog.load::<ClientConfig>(&provenance.project).await?;
+            let installed = InstalledBuild {
+                project: provenance.project,
+                version: provenance.version,
+                build: provenance.build,
+                identified_by: IdentifiedBy::Provenance,
+            };
+            return Ok((Some(jar.clone()), Some(installed)));
+        }
+        if let Some((project, build)) = catalog.find_by_sha256(&sha256) {
+            let installed = InstalledBuild {
+                project: project.to_string(),
+                version: build.version.clone(),
+                build: build.build,
+                identified_by: IdentifiedBy::Hash,
+            };
+            return Ok((Some(jar.clone()), Some(installed)));
+        }
+    }
+
+    let history = directory.join("version_history.json");
+    let jar = jars.into_iter().next();
+    if !history.exists() {
+        return Ok((jar, None));
+    }
+    match VersionHistory::from_path(&history)?.current() {
+        Some((project, version, build)) => {
+            catalog.load::<ClientConfig>(&project).await?;
+            Ok((jar, Some(InstalledBuild { project, version, build, identified_by: IdentifiedBy::VersionHistory })))
+        }
+        None => Ok((jar, None)),
+    }
+}
+
+/// Compares an installed build against the newest build of its version in `catalog`.
+pub fn server_status(catalog: &BuildCatalog, directory: &Path, jar: Option<PathBuf>, installed: InstalledBuild, now: DateTime<Utc>, policy: &SupportPolicy) -> Result<ServerStatus> {
+    let project = catalog.project(&installed.project)
+        .ok_or_else(|| format!("Project {} is not loaded.", installed.project))?;
+    let builds = catalog.builds(&installed.project, &installed.version);
+    let latest = builds.iter().copied().max_by_key(|build| build.build);
+    let current = catalog.build(&installed.project, &installed.version, installed.build);
+    let age_days = match current {
+        Some(build) => Some((now - build.parsed_time()?).num_days()),
+        None => None,
+    };
+
+    Ok(ServerStatus {
+        directory: directory.display().to_string(),
+        jar: jar.map(|jar| jar.display().to_string()),
+        build_time: current.map(|build| build.time.clone()),
+        latest_build: latest.map(|build| build.build),
+        builds_behind: builds.iter().filter(|build| build.build > installed.build).count(),
+        age_days,
+        support: Some(assess_version(project, &installed.version, latest, now, policy)?),
+        installed: Some(installed),
+        error: None,
+    })
+}
+
+/// Identifies and assesses every server directory. `projects` are loaded up front for hash
+/// lookups; failures are reported per server rather than aborting the report.
+pub async fn fleet_report<ClientConfig>(directories: &[PathBuf], projects: &[String], policy: &SupportPolicy) -> Result<Vec<ServerStatus>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let mut catalog = BuildCatalog::new();
+    for project in projects {
+        catalog.load::<ClientConfig>(project).await?;
+    }
+
+    let now = Utc::now();
+    let mut report = Vec::with_capacity(directories.len());
+    for directory in directories {
+        let status = match identify_server::<ClientConfig>(&mut catalog, directory).await {
+            Ok((jar, Some(installed))) => server_status(&catalog, directory, jar, installed, now, policy)
+                .unwrap_or_else(|error| ServerStatus::failed(directory, None, error.to_string())),
+            Ok((jar, None)) => ServerStatus::failed(directory, jar.map(|jar| jar.display().to_string()), String::from("No known jar or version_history.json.")),
+            Err(error) => ServerStatus::failed(directory, None, error.to_string()),
+        };
+        report.push(status);
+    }
+    Ok(report)
+}
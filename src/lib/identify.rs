@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use super::download::sha256_file;
+#[cfg(feature = "index")]
+use super::index::BuildIndex;
+use super::paper::{BuildInfo, ProjectResponse};
+use super::provenance::Provenance;
+use super::{PaperClientConfig, Result};
+
+/// The build a jar was downloaded from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdentifiedJar {
+    pub project: String,
+    pub sha256: String,
+    pub build: BuildInfo,
+    /// The sidecar the jar was identified through.
+    pub provenance: Option<Provenance>,
+}
+
+/// Finds the build of `project` whose download has `sha256`, searching the newest version
+/// groups first.
+pub async fn find_build_by_sha256<ClientConfig>(project: &str, sha256: &str) -> Result<Option<BuildInfo>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project_info = ClientConfig::get_project(project).await?;
+    for group in project_info.version_groups.iter().rev() {
+        let response = ClientConfig::get_group_builds(project, group.as_str()).await?;
+        if let Some(build) = response.builds.into_iter().find(|build| build.downloads.application.sha256.eq_ignore_ascii_case(sha256)) {
+            return Ok(Some(build));
+        }
+    }
+    Ok(None)
+}
+
+async fn identified<ClientConfig>(project: &str, version: &str, build: i32, sha256: String, provenance: Option<Provenance>) -> Result<IdentifiedJar>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let response = ClientConfig::get_version_builds(project, version, build).await?;
+    Ok(IdentifiedJar { project: project.to_string(), sha256, build: response.into(), provenance })
+}
+
+/// Identifies the jar at `path` through its provenance sidecar if it still matches `sha256`.
+async fn identify_by_sidecar<ClientConfig>(path: &Path, sha256: &str) -> Result<Option<IdentifiedJar>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    match Provenance::read_verified(path, sha256)? {
+        Some(provenance) => {
+            let (project, version, build) = (provenance.project.clone(), provenance.version.clone(), provenance.build);
+            Ok(Some(identified::<ClientConfig>(&project, &version, build, sha256.to_string(), Some(provenance)).await?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Identifies the jar at `path` through its provenance sidecar if it still matches the jar,
+/// otherwise by looking its sha256 up in the builds of `project`.
+pub async fn identify_jar<ClientConfig, P>(project: &str, path: P) -> Result<Option<IdentifiedJar>>
+    where ClientConfig: PaperClientConfig + Send, P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let sha256 = sha256_file(path)?;
+    if let Some(identified) = identify_by_sidecar::<ClientConfig>(path, &sha256).await? {
+        return Ok(Some(identified));
+    }
+    Ok(find_build_by_sha256::<ClientConfig>(project, &sha256).await?
+        .map(|build| IdentifiedJar { project: project.to_string(), sha256, build, provenance: None }))
+}
+
+/// Like [`identify_jar`], but looks the sha256 up in the builds of every project in `index`
+/// instead of fetching the builds of one project.
+#[cfg(feature = "index")]
+pub async fn identify_jar_indexed<ClientConfig, P>(index: &BuildIndex, path: P) -> Result<Option<IdentifiedJar>>
+    where ClientConfig: PaperClientConfig + Send, P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let sha256 = sha256_file(path)?;
+    if let Some(identified) = identify_by_sidecar::<ClientConfig>(path, &sha256).await? {
+        return Ok(Some(identified));
+    }
+    match index.find_by_sha256(&sha256)? {
+        Some(download) => Ok(Some(identified::<ClientConfig>(&download.project, &download.version, download.build, sha256, None).await?)),
+        None => Ok(None),
+    }
+}
+
+/// The `version_history.json` a server writes next to its jar on startup.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionHistory {
+    pub current_version: String,
+    #[serde(default)]
+    pub old_version: Option<String>,
+}
+
+impl VersionHistory {
+    pub fn from_path<P>(path: P) -> Result<Self> where P: AsRef<Path> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// The project, version and build the server last started with.
+    pub fn current(&self) -> Option<(String, String, i32)> {
+        parse_version_string(&self.current_version)
+    }
+}
+
+static VERSION_STRING: Lazy<Regex> = Lazy::new(|| Regex::new(r"git-(\w+)-(\d+) \(MC: ([^)]+)\)").expect("Version pattern must compile."));
+
+/// Parses a server version string such as `git-Paper-794 (MC: 1.16.5)` into its lowercase
+/// project, Minecraft version and build.
+pub fn parse_version_string(version: &str) -> Option<(String, String, i32)> {
+    let captures = VERSION_STRING.captures(version)?;
+    Some((captures[1].to_lowercase(), captures[3].trim().to_string(), captures[2].parse().ok()?))
+}
+
+/// The projects and builds of several projects, fetched once, for identifying many jars.
+#[derive(Debug, Clone, Default)]
+pub struct BuildCatalog {
+    projects: HashMap<String, (ProjectResponse, Vec<BuildInfo>)>,
+}
+
+impl BuildCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetches the project and all of its group builds unless already loaded.
+    pub async fn load<ClientConfig>(&mut self, project: &str) -> Result<()>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        if self.projects.contains_key(project) {
+            return Ok(());
+        }
+        let project_info = ClientConfig::get_project(project).await?;
+        let mut builds = Vec::new();
+        for group in &project_info.version_groups {
+            builds.extend(ClientConfig::get_group_builds(project, group.as_str()).await?.builds);
+        }
+        self.projects.insert(project.to_string(), (project_info, builds));
+        Ok(())
+    }
+
+    pub fn project(&self, project: &str) -> Option<&ProjectResponse> {
+        self.projects.get(project).map(|(project, _)| project)
+    }
+
+    /// The loaded builds of a version.
+    pub fn builds(&self, project: &str, version: &str) -> Vec<&BuildInfo> {
+        self.projects.get(project)
+            .map(|(_, builds)| builds.iter().filter(|build| build.version == version).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn build(&self, project: &str, version: &str, build: i32) -> Option<&BuildInfo> {
+        self.builds(project, version).into_iter().find(|info| info.build == build)
+    }
+
+    /// The loaded project and build whose download has `sha256`.
+    pub fn find_by_sha256(&self, sha256: &str) -> Option<(&str, &BuildInfo)> {
+        self.projects.iter().find_map(|(project, (_, builds))| builds.iter()
+            .find(|build| build.downloads.application.sha256.eq_ignore_ascii_case(sha256))
+            .map(|build| (project.as_str(), build)))
+    }
+}
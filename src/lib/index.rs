@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, NO_PARAMS, params};
+use serde::{Serialize, Deserialize};
+
+use super::paper::{BuildInfo, ProjectResponse};
+use super::resolve::GroupBuilds;
+use super::version::group_of;
+use super::{PaperClientConfig, Result};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS projects (
+    project_id TEXT PRIMARY KEY,
+    project_name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS versions (
+    project_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    version_group TEXT,
+    position INTEGER NOT NULL,
+    builds TEXT NOT NULL,
+    PRIMARY KEY (project_id, version)
+);
+CREATE TABLE IF NOT EXISTS builds (
+    project_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    build INTEGER NOT NULL,
+    time TEXT NOT NULL,
+    PRIMARY KEY (project_id, version, build)
+);
+CREATE TABLE IF NOT EXISTS changes (
+    project_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    build INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    commit_hash TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    message TEXT NOT NULL,
+    PRIMARY KEY (project_id, version, build, position)
+);
+CREATE TABLE IF NOT EXISTS downloads (
+    project_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    build INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    PRIMARY KEY (project_id, version, build, kind)
+);
+CREATE INDEX IF NOT EXISTS downloads_sha256 ON downloads (sha256);
+";
+
+/// What an [`BuildIndex::sync`] run checked and added.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndexReport {
+    pub projects: usize,
+    pub versions_checked: usize,
+    pub versions_updated: usize,
+    pub builds_added: usize,
+    /// Builds no longer listed by their version, such as withdrawn ones.
+    pub builds_removed: usize,
+}
+
+/// A build found by its download hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedDownload {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    pub time: String,
+    pub name: String,
+    pub sha256: String,
+}
+
+/// The columns and rows of a query, with values converted to JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+/// A local SQLite copy of the projects, versions, builds, changes and download hashes of the API.
+pub struct BuildIndex {
+    connection: Connection,
+}
+
+impl BuildIndex {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens an existing index which can only be read, as used for queries and hash lookups.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self { connection: Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)? })
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Syncs `projects`, or every project when `None`. Only versions whose build list changed
+    /// since the last sync have their builds fetched, from their version group when possible, and
+    /// builds they no longer list are removed.
+    pub async fn sync<ClientConfig>(&mut self, projects: Option<&[String]>) -> Result<IndexReport>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let projects = match projects {
+            Some(projects) => projects.to_vec(),
+            None => ClientConfig::get_projects().await?.projects,
+        };
+
+        let mut report = IndexReport::default();
+        for project in projects {
+            let project_info = ClientConfig::get_project(project.as_str()).await?;
+            self.store_project(&project_info)?;
+            report.projects += 1;
+
+            let mut group_builds = GroupBuilds::new(&project_info);
+            for (position, version) in project_info.versions.iter().enumerate() {
+                let group = group_of(version, &project_info.version_groups);
+                let version_info = ClientConfig::get_version_info(project_info.project_id.as_str(), version.as_str()).await?;
+                let signature = serde_json::to_string(&version_info.builds)?;
+                report.versions_checked += 1;
+                if self.stored_signature(&project_info.project_id, version)?.as_ref() == Some(&signature) {
+                    continue;
+                }
+
+                let known = self.stored_builds(&project_info.project_id, version)?;
+                let missing: Vec<i32> = version_info.builds.iter().copied().filter(|build| !known.contains(build)).collect();
+                let removed: Vec<i32> = known.into_iter().filter(|build| !version_info.builds.contains(build)).collect();
+                let builds = group_builds.builds::<ClientConfig>(version, &missing).await?;
+
+                report.builds_added += builds.len();
+                report.builds_removed += removed.len();
+                report.versions_updated += 1;
+                self.remove_builds(&project_info.project_id, version, &removed)?;
+                self.store_version(&project_info.project_id, version, group.map(String::as_str), position, &signature, &builds)?;
+            }
+        }
+        Ok(report)
+    }
+
+    fn store_project(&self, project: &ProjectResponse) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO projects (project_id, project_name) VALUES (?1, ?2)",
+            params![project.project_id, project.project_name],
+        )?;
+        Ok(())
+    }
+
+    fn stored_signature(&self, project: &str, version: &str) -> Result<Option<String>> {
+        Ok(self.connection.query_row(
+            "SELECT builds FROM versions WHERE project_id = ?1 AND version = ?2",
+            params![project, version],
+            |row| row.get(0),
+        ).optional()?)
+    }
+
+    fn stored_builds(&self, project: &str, version: &str) -> Result<Vec<i32>> {
+        let mut statement = self.connection.prepare("SELECT build FROM builds WHERE project_id = ?1 AND version = ?2")?;
+        let builds = statement.query_map(params![project, version], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i32>>>()?;
+        Ok(builds)
+    }
+
+    fn remove_builds(&mut self, project: &str, version: &str, builds: &[i32]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for build in builds {
+            for table in &["builds", "changes", "downloads"] {
+                transaction.execute(
+                    &format!("DELETE FROM {} WHERE project_id = ?1 AND version = ?2 AND build = ?3", table),
+                    params![project, version, build],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn store_version(&mut self, project: &str, version: &str, group: Option<&str>, position: usize, signature: &str, builds: &[BuildInfo]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for build in builds {
+            transaction.execute(
+                "INSERT OR REPLACE INTO builds (project_id, version, build, time) VALUES (?1, ?2, ?3, ?4)",
+                params![project, version, build.build, build.time],
+            )?;
+            transaction.execute(
+                "DELETE FROM changes WHERE project_id = ?1 AND version = ?2 AND build = ?3",
+                params![project, version, build.build],
+            )?;
+            for (position, change) in build.changes.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO changes (project_id, version, build, position, commit_hash, summary, message) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![project, version, build.build, position as i64, change.commit, change.summary, change.message],
+                )?;
+            }
+            let application = &build.downloads.application;
+            transaction.execute(
+                "INSERT OR REPLACE INTO downloads (project_id, version, build, kind, name, sha256) VALUES (?1, ?2, ?3, 'application', ?4, ?5)",
+                params![project, version, build.build, application.name, application.sha256.to_ascii_lowercase()],
+            )?;
+        }
+        transaction.execute(
+            "INSERT OR REPLACE INTO versions (project_id, version, version_group, position, builds) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![project, version, group, position as i64, signature],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// The build whose download has the given sha256.
+    pub fn find_by_sha256(&self, sha256: &str) -> Result<Option<IndexedDownload>> {
+        Ok(self.connection.query_row(
+            "SELECT d.project_id, d.version, d.build, b.time, d.name, d.sha256 FROM downloads d \
+             JOIN builds b ON b.project_id = d.project_id AND b.version = d.version AND b.build = d.build \
+             WHERE d.sha256 = ?1 LIMIT 1",
+            params![sha256.to_ascii_lowercase()],
+            |row| Ok(IndexedDownload {
+                project: row.get(0)?,
+                version: row.get(1)?,
+                build: row.get(2)?,
+                time: row.get(3)?,
+                name: row.get(4)?,
+                sha256: row.get(5)?,
+            }),
+        ).optional()?)
+    }
+
+    /// Runs a single SQL statement against the index. Open it with [`BuildIndex::open_read_only`]
+    /// to run statements from users.
+    pub fn query(&self, sql: &str) -> Result<QueryResult> {
+        let mut statement = self.connection.prepare(sql)?;
+        let columns: Vec<String> = statement.column_names().into_iter().map(String::from).collect();
+        let mut rows = statement.query(NO_PARAMS)?;
+        let mut result = QueryResult { columns, rows: Vec::new() };
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(result.columns.len());
+            for i in 0..result.columns.len() {
+                values.push(match row.get_raw(i) {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(integer) => serde_json::Value::from(integer),
+                    ValueRef::Real(real) => serde_json::Value::from(real),
+                    ValueRef::Text(text) => serde_json::Value::from(String::from_utf8_lossy(text).into_owned()),
+                    ValueRef::Blob(blob) => serde_json::Value::from(blob.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+                });
+            }
+            result.rows.push(values);
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt::{Display, Formatter};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use tokio::process::Command;
+
+use super::paper::ProjectVersionInfoResponse;
+use super::version::{compare_versions, split_pre_release};
+use super::Result;
+
+/// The Java versions of Minecraft server releases, by the first version each range applies to.
+/// Releases before 1.12 are unreliable past Java 11; later maxima are the newest Java known to
+/// run them.
+const SERVER_JAVA: &[(&str, u32, Option<u32>)] = &[
+    ("1.8", 8, Some(11)),
+    ("1.12", 8, Some(16)),
+    ("1.16.5", 8, Some(17)),
+    ("1.17", 16, Some(17)),
+    ("1.18", 17, None),
+    ("1.20.5", 21, None),
+];
+
+const VELOCITY_JAVA: &[(&str, u32, Option<u32>)] = &[
+    ("1.0.0", 8, None),
+    ("3.0.0", 11, None),
+    ("3.3.0", 17, None),
+];
+
+/// The Java feature releases a build runs on, e.g. 16 through 17.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JavaRange {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+impl JavaRange {
+    pub fn status(&self, java: u32) -> JavaStatus {
+        if java < self.min {
+            JavaStatus::TooOld
+        } else if self.max.is_some_and(|max| java > max) {
+            JavaStatus::TooNew
+        } else {
+            JavaStatus::Compatible
+        }
+    }
+}
+
+impl Display for JavaRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "Java {}", self.min),
+            Some(max) => write!(f, "Java {}-{}", self.min, max),
+            None => write!(f, "Java {}+", self.min),
+        }
+    }
+}
+
+/// The Java range of a version of `project`, `None` for projects or versions without one.
+/// Pre-releases such as `1.20.5-rc1` need the Java of their release.
+pub fn java_requirement(project: &str, version: &str) -> Option<JavaRange> {
+    let table = match project.to_ascii_lowercase().as_str() {
+        "paper" | "folia" => SERVER_JAVA,
+        "velocity" => VELOCITY_JAVA,
+        _ => return None,
+    };
+    let (release, _) = split_pre_release(version);
+    table.iter().rev()
+        .find(|(first, _, _)| compare_versions(first, release) != std::cmp::Ordering::Greater)
+        .map(|&(_, min, max)| JavaRange { min, max })
+}
+
+/// The Java range of the builds of a version.
+pub fn required_java(version: &ProjectVersionInfoResponse) -> Option<JavaRange> {
+    java_requirement(&version.project_id, &version.version)
+}
+
+static JAVA_VERSION: Lazy<Regex> = Lazy::new(|| Regex::new(r#"version "(\d+)(?:\.(\d+))?"#).expect("Java version pattern must compile."));
+
+/// Parses the feature release out of `java -version` output, e.g. 8 for `"1.8.0_292"` and 16 for
+/// `"16.0.1"`.
+pub fn parse_java_version(output: &str) -> Option<u32> {
+    let captures = JAVA_VERSION.captures(output)?;
+    let major = captures[1].parse::<u32>().ok()?;
+    match (major, captures.get(2)) {
+        (1, Some(minor)) => minor.as_str().parse().ok(),
+        _ => Some(major),
+    }
+}
+
+/// Runs `<java> -version` and parses its feature release.
+pub async fn detect_java(java: &str) -> Result<u32> {
+    let output = Command::new(java).arg("-version").output().await
+        .map_err(|error| format!("Could not run {}: {}", java, error))?;
+    // The version goes to stderr, though some builds print it to stdout.
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stderr), String::from_utf8_lossy(&output.stdout));
+    parse_java_version(&text).ok_or_else(|| Box::from(format!("Could not read the version of {}.", java)))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JavaStatus {
+    Compatible,
+    TooOld,
+    TooNew,
+}
+
+/// How a Java runtime fits the range of a version.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JavaCheck {
+    pub project: String,
+    pub version: String,
+    pub java: u32,
+    pub required: JavaRange,
+    pub status: JavaStatus,
+}
+
+impl JavaCheck {
+    pub fn message(&self) -> String {
+        match self.status {
+            JavaStatus::Compatible => format!("Java {} runs {} {} ({}).", self.java, self.project, self.version, self.required),
+            JavaStatus::TooOld => format!("Java {} is too old for {} {}, which needs {}.", self.java, self.project, self.version, self.required),
+            JavaStatus::TooNew => format!("Java {} is newer than {} {} supports ({}).", self.java, self.project, self.version, self.required),
+        }
+    }
+}
+
+/// Checks the runtime at `java` against the range of a version, `None` when the version has no
+/// known range. Fails when `java` cannot be run.
+pub async fn check_java(java: &str, project: &str, version: &str) -> Result<Option<JavaCheck>> {
+    let required = match java_requirement(project, version) {
+        Some(required) => required,
+        None => return Ok(None),
+    };
+    let detected = detect_java(java).await?;
+    Ok(Some(JavaCheck {
+        project: project.to_string(),
+        version: version.to_string(),
+        java: detected,
+        required,
+        status: required.status(detected),
+    }))
+}
+
+/// Checks `java` before installing a version: a runtime which is too old fails unless `ignore`
+/// is set, anything else worth knowing, such as a runtime which could not be run, is returned as
+/// a warning.
+pub async fn enforce_java(java: &str, project: &str, version: &str, ignore: bool) -> Result<Option<String>> {
+    match check_java(java, project, version).await {
+        Ok(Some(check)) => match check.status {
+            JavaStatus::Compatible => Ok(None),
+            JavaStatus::TooNew => Ok(Some(check.message())),
+            JavaStatus::TooOld if ignore => Ok(Some(check.message())),
+            JavaStatus::TooOld => Err(Box::from(check.message())),
+        },
+        Ok(None) => Ok(None),
+        Err(error) => Ok(Some(format!("{} Java compatibility was not checked.", error))),
+    }
+}
@@ -1,4 +1,32 @@
 pub mod paper;
+pub mod download;
+pub mod notes;
+pub mod search;
+pub mod classify;
+pub mod feed;
+pub mod version;
+pub mod resolve;
+pub mod stats;
+pub mod support;
+pub mod matrix;
+pub mod identify;
+pub mod java;
+pub mod provenance;
+pub mod store;
+pub mod fleet;
+pub mod rollout;
+pub mod scaffold;
+pub mod watch;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock;
+#[cfg(any(test, feature = "mock-server", feature = "mirror"))]
+pub mod server;
+#[cfg(any(test, feature = "mirror"))]
+pub mod mirror;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "index")]
+pub mod index;
 
 extern crate hyper;
 
@@ -6,23 +34,55 @@ use async_trait::async_trait;
 use hyper::{Client, StatusCode, Body, Response};
 use hyper::body::HttpBody;
 use paper::*;
-use hyper_tls::HttpsConnector;
 use hyper::client::HttpConnector;
 use bytes::buf::BufExt;
 use bytes::Buf;
 use bytes::buf::ext::Reader;
 use serde::de::DeserializeOwned;
+use download::DownloadStream;
+use once_cell::sync::OnceCell;
 
 pub const BASE_URL: &str = "https://papermc.io/api";
+pub const BASE_URL_VAR: &str = "PAPER_API_URL";
+
+static BASE_URL_OVERRIDE: OnceCell<String> = OnceCell::new();
+
+/// Points the default [`PaperClientConfig::base_url`] at `url`, ahead of `PAPER_API_URL`. Unlike
+/// setting the variable, this is sound while other threads run. Fails when already set.
+pub fn set_base_url<T>(url: T) -> Result<()> where T: Into<String> {
+    BASE_URL_OVERRIDE.set(url.into()).map_err(|_| Box::from("The base url is already set."))
+}
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+/// Returned when the API answers with a status other than 200, carrying the response body.
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.body)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
 #[async_trait]
 pub trait PaperClientConfig {
-    type ConfigType: PaperClientConfig;
+    type ConfigType: PaperClientConfig + Send;
 
     fn debug() -> bool;
 
+    fn base_url() -> String {
+        if let Some(url) = BASE_URL_OVERRIDE.get() {
+            return url.clone();
+        }
+        std::env::var(BASE_URL_VAR).unwrap_or_else(|_| String::from(BASE_URL))
+    }
+
     async fn get_projects() -> Result<ProjectsResponse> {
         ProjectsRequest::new().call::<Self::ConfigType>().await
     }
@@ -46,6 +106,12 @@ pub trait PaperClientConfig {
     async fn get_version_builds<T>(project: T, version: T, build: i32) -> Result<ProjectVersionBuildsResponse> where T: Into<String> + Send {
         ProjectVersionBuildsRequest::new(project, version, build).call::<Self::ConfigType>().await
     }
+
+    /// All builds of a version with their changes, read from its version group's builds when possible.
+    async fn get_builds_of_version<T>(project: T, version: T) -> Result<Vec<BuildInfo>> where T: Into<String> + Send {
+        let project_info = Self::ConfigType::get_project(project).await?;
+        resolve::GroupBuilds::new(&project_info).version::<Self::ConfigType>(&version.into()).await
+    }
 }
 
 pub struct PaperClientDebug;
@@ -68,22 +134,31 @@ impl PaperClientConfig for PaperClient {
     }
 }
 
-fn build_url(path: &String) -> String {
-    let mut final_url = String::from(BASE_URL);
-    final_url.push_str(path.as_str());
+fn build_url(base_url: &str, path: &str) -> String {
+    let mut final_url = String::from(base_url.trim_end_matches('/'));
+    final_url.push_str(path);
     final_url
 }
 
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("Either the \"native-tls\" or the \"rustls\" feature must be enabled.");
+
+#[cfg(feature = "rustls")]
+type HttpsConnector<T> = hyper_rustls::HttpsConnector<T>;
+
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+type HttpsConnector<T> = hyper_tls::HttpsConnector<T>;
+
 fn build_client() -> Client<HttpsConnector<HttpConnector>> {
     let https = HttpsConnector::new();
     Client::builder().build::<_, hyper::Body>(https)
 }
 
-async fn get_reader<ClientConfig>(path: String) -> Result<Reader<impl Buf>>
+async fn get_response<ClientConfig>(path: &str) -> Result<Response<Body>>
     where ClientConfig: PaperClientConfig
 {
     let client = build_client();
-    let uri = build_url(&path).parse()?;
+    let uri = build_url(&ClientConfig::base_url(), path).parse()?;
     if ClientConfig::debug() {
         println!("GETTING {}", uri);
     }
@@ -97,51 +172,68 @@ async fn get_reader<ClientConfig>(path: String) -> Result<Reader<impl Buf>>
         while let Some(chunk) = client_response.body_mut().data().await {
             error.push_str(&String::from_utf8_lossy(&chunk?));
         }
-        return Err(Box::from(error));
+        return Err(Box::new(StatusError { status: client_response.status(), body: error }));
     }
 
+    Ok(client_response)
+}
+
+async fn get_reader<ClientConfig>(path: String) -> Result<Reader<impl Buf>>
+    where ClientConfig: PaperClientConfig
+{
+    let client_response = get_response::<ClientConfig>(&path).await?;
     let buf = hyper::body::aggregate(client_response).await?;
     let bytes = buf.reader();
     Ok(bytes)
 }
 
-pub async fn download_file<ClientConfig, Function>(path: String, mut downloader: Function) -> Result<()>
-    where ClientConfig: PaperClientConfig,
-          Function: FnMut(&[u8])
-{
-    let client = build_client();
-    let uri = build_url(&path).parse()?;
-
-    if ClientConfig::debug() {
-        println!("GETTING {}", uri);
-    }
-    let mut client_response: Response<Body> = client.get(uri).await?;
-    if ClientConfig::debug() {
-        println!("Response: {}", client_response.status());
+/// Posts `body` as JSON to an absolute `url`, such as a webhook.
+pub async fn post_json<T>(url: &str, body: &T) -> Result<()> where T: serde::Serialize {
+    let request = hyper::Request::post(url)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?;
+    let response = build_client().request(request).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        return Err(Box::new(StatusError { status, body: String::from_utf8_lossy(&body).into_owned() }));
     }
+    Ok(())
+}
 
-    if client_response.status().ne(&StatusCode::from_u16(200)?) {
-        let mut error = String::from("");
-        while let Some(chunk) = client_response.body_mut().data().await {
-            error.push_str(&String::from_utf8_lossy(&chunk?));
-        }
-        return Err(Box::from(error));
-    }
+/// Fetches the raw body of `path`, relative to the configured base url.
+pub async fn get_bytes<ClientConfig>(path: String) -> Result<bytes::Bytes>
+    where ClientConfig: PaperClientConfig
+{
+    let client_response = get_response::<ClientConfig>(&path).await?;
+    Ok(hyper::body::to_bytes(client_response.into_body()).await?)
+}
 
-    let mut total = 0;
+pub async fn download_stream<ClientConfig>(path: String) -> Result<DownloadStream>
+    where ClientConfig: PaperClientConfig
+{
+    let client_response = get_response::<ClientConfig>(&path).await?;
+    Ok(DownloadStream::new(client_response))
+}
 
-    let size_hint = client_response.size_hint();
-    let size = size_hint.upper().unwrap_or(size_hint.lower());
+pub async fn download_file<ClientConfig, Function>(path: String, mut downloader: Function) -> Result<()>
+    where ClientConfig: PaperClientConfig,
+          Function: FnMut(&[u8])
+{
+    try_download_file::<ClientConfig, _>(path, |bytes| {
+        downloader(bytes);
+        Ok(())
+    }).await
+}
 
-    while let Some(chunk) = client_response.body_mut().data().await {
-        let bytes = chunk?;
-        if ClientConfig::debug() {
-            total = total + bytes.len();
-            print!("\rDownloaded: ({}/{}){}%", total, size, (total as u64 * 100u64) / size);
-        }
-        downloader(&bytes);
-    }
-    println!();
+/// Like `download_file`, but stops at the first error `downloader` returns, e.g. a failed write.
+pub async fn try_download_file<ClientConfig, Function>(path: String, mut downloader: Function) -> Result<()>
+    where ClientConfig: PaperClientConfig,
+          Function: FnMut(&[u8]) -> Result<()>
+{
+    download_stream::<ClientConfig>(path).await?
+        .for_each_chunk(ClientConfig::debug(), |bytes| futures_util::future::ready(downloader(&bytes)))
+        .await?;
     Ok(())
 }
 
@@ -151,11 +243,14 @@ pub async fn call_request<T, S>(request: &S) -> Result<S::Response>
         S: Request + Send + Sync,
         S::Response: DeserializeOwned,
 {
+    request.validate()?;
     let reader = get_reader::<T>(request.build_request_url()).await?;
     let value = serde_json::from_reader(reader)?;
 
     Ok(value)
 }
 
+// The baseline `sanity` test asserts a constant, which newer clippy versions reject.
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod test;
\ No newline at end of file
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use futures_util::future::try_join_all;
+use serde::{Serialize, Deserialize};
+
+use super::paper::BuildInfo;
+use super::resolve::latest_at;
+use super::version::{compare_versions, group_of};
+use super::{PaperClientConfig, Result};
+
+/// The newest build of a version.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatestBuild {
+    pub build: i32,
+    pub time: String,
+    pub age_hours: f64,
+}
+
+/// What one project supports, keyed by version.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectSupport {
+    pub project_id: String,
+    pub project_name: String,
+    pub version_groups: Vec<String>,
+    pub versions: Vec<String>,
+    pub latest: BTreeMap<String, LatestBuild>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatrixRow {
+    pub version: String,
+    pub group: Option<String>,
+    /// One cell per project, in the order of [`CompatibilityMatrix::projects`]. `None` when the
+    /// project has no build of the version.
+    pub cells: Vec<Option<LatestBuild>>,
+    /// Whether each project supports the version's group, through any of its versions.
+    pub group_supported: Vec<bool>,
+}
+
+/// Which versions several projects support, newest version first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompatibilityMatrix {
+    pub generated_at: String,
+    pub projects: Vec<ProjectSupport>,
+    pub rows: Vec<MatrixRow>,
+}
+
+async fn project_support<ClientConfig>(project: &str, now: DateTime<Utc>) -> Result<ProjectSupport>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project_info = ClientConfig::get_project(project).await?;
+    let groups = try_join_all(project_info.version_groups.iter()
+        .map(|group| ClientConfig::get_group_builds(project_info.project_id.as_str(), group.as_str()))).await?;
+    let builds: Vec<BuildInfo> = groups.into_iter().flat_map(|group| group.builds).collect();
+
+    let mut latest = BTreeMap::new();
+    for version in &project_info.versions {
+        let version_builds: Vec<BuildInfo> = builds.iter().filter(|build| &build.version == version).cloned().collect();
+        if let Some(build) = latest_at(&version_builds, now)? {
+            let age = now - build.parsed_time()?;
+            latest.insert(version.clone(), LatestBuild {
+                build: build.build,
+                time: build.time.clone(),
+                age_hours: age.num_seconds() as f64 / 3600.0,
+            });
+        }
+    }
+
+    Ok(ProjectSupport {
+        project_id: project_info.project_id,
+        project_name: project_info.project_name,
+        version_groups: project_info.version_groups,
+        versions: project_info.versions,
+        latest,
+    })
+}
+
+/// Fetches `projects` concurrently and lays their versions out side by side.
+pub async fn compatibility_matrix<ClientConfig>(projects: &[String]) -> Result<CompatibilityMatrix>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let now = Utc::now();
+    let projects = try_join_all(projects.iter().map(|project| project_support::<ClientConfig>(project, now))).await?;
+    Ok(build_matrix(projects, now))
+}
+
+pub fn build_matrix(projects: Vec<ProjectSupport>, now: DateTime<Utc>) -> CompatibilityMatrix {
+    let mut versions: Vec<&String> = projects.iter().flat_map(|project| project.versions.iter()).collect();
+    versions.sort_by(|a, b| compare_versions(b, a));
+    versions.dedup();
+
+    let rows = versions.into_iter().map(|version| {
+        let group = projects.iter().find_map(|project| group_of(version, &project.version_groups)).cloned();
+        MatrixRow {
+            version: version.clone(),
+            cells: projects.iter().map(|project| project.latest.get(version).cloned()).collect(),
+            group_supported: projects.iter()
+                .map(|project| group.as_ref().is_some_and(|group| project.version_groups.contains(group)))
+                .collect(),
+            group,
+        }
+    }).collect();
+
+    CompatibilityMatrix { generated_at: now.to_rfc3339(), projects, rows }
+}
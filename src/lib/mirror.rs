@@ -0,0 +1,302 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::oneshot;
+use tokio::task;
+
+use super::download::{object_path, sha256_file, write_atomic};
+use super::paper::{BuildDownloadRequest, ProjectGroupInfoResponse, ProjectResponse, ProjectVersionBuildsResponse, ProjectVersionInfoResponse};
+use super::server::{Route, error, json_bytes, not_found, serve_file};
+use super::version::VersionSpec;
+use super::{PaperClientConfig, Result, StatusError, get_bytes};
+
+/// The on-disk layout of a mirror: API responses under `api/`, jars under `objects/` keyed by sha256.
+#[derive(Debug, Clone)]
+pub struct MirrorStore {
+    root: PathBuf,
+}
+
+impl MirrorStore {
+    pub fn new<P>(root: P) -> Self where P: AsRef<Path> {
+        Self { root: root.as_ref().to_path_buf() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The response file of a validated, non-download route.
+    pub fn metadata_path(&self, route: &Route) -> PathBuf {
+        let mut path = self.root.join("api");
+        for segment in route.path().split('/').filter(|segment| !segment.is_empty()) {
+            path.push(segment);
+        }
+        path.join("index.json")
+    }
+
+    pub fn object_path(&self, sha256: &str) -> Result<PathBuf> {
+        object_path(&self.root, sha256)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// How long project, group and version listings are served before being refreshed upstream.
+    /// Build responses and jars never change and are kept indefinitely.
+    pub ttl: Duration,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        Self { ttl: Duration::from_secs(300) }
+    }
+}
+
+struct MirrorState<ClientConfig> {
+    store: MirrorStore,
+    options: MirrorOptions,
+    config: PhantomData<fn() -> ClientConfig>,
+}
+
+/// A read-through caching mirror of the v2 API, fetching misses through `ClientConfig`.
+pub struct MirrorServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MirrorServer {
+    pub async fn start<ClientConfig>(store: MirrorStore, options: MirrorOptions, addr: SocketAddr) -> Result<Self>
+        where ClientConfig: PaperClientConfig + Send + 'static
+    {
+        let state = Arc::new(MirrorState::<ClientConfig> { store, options, config: PhantomData });
+        let make_service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+            }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, receiver) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            receiver.await.ok();
+        }));
+
+        Ok(Self { addr, shutdown: Some(shutdown) })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for MirrorServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle<ClientConfig>(state: Arc<MirrorState<ClientConfig>>, request: Request<Body>) -> std::result::Result<Response<Body>, Infallible>
+    where ClientConfig: PaperClientConfig + Send
+{
+    if request.method() != Method::GET {
+        return Ok(error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed."));
+    }
+    let route = match Route::parse(request.uri().path()) {
+        Some(route) => route,
+        None => return Ok(not_found()),
+    };
+    if let Err(e) = route.validate() {
+        return Ok(error(StatusCode::BAD_REQUEST, &e.to_string()));
+    }
+
+    let response = match &route {
+        Route::Download(..) => serve_download(&state, &request, &route).await,
+        _ => cached_response(&state, &route).await.map(json_bytes),
+    };
+    Ok(response.unwrap_or_else(|e| match e.downcast_ref::<StatusError>() {
+        Some(status_error) => error(status_error.status, &status_error.body),
+        None => error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    }))
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path).await.map(|metadata| metadata.is_file()).unwrap_or(false)
+}
+
+async fn is_fresh(path: &Path, route: &Route, ttl: Duration) -> bool {
+    if let Route::VersionBuilds(..) = route {
+        return is_file(path).await;
+    }
+    tokio::fs::metadata(path).await
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| SystemTime::now().duration_since(modified).map(|age| age < ttl).unwrap_or(true))
+        .unwrap_or(false)
+}
+
+/// Answers from the store when fresh, otherwise refreshes from upstream, falling back to a stale
+/// copy when upstream is unavailable.
+async fn cached_response<ClientConfig>(state: &MirrorState<ClientConfig>, route: &Route) -> Result<Vec<u8>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let path = state.store.metadata_path(route);
+    if is_fresh(&path, route, state.options.ttl).await {
+        return Ok(tokio::fs::read(&path).await?);
+    }
+
+    match get_bytes::<ClientConfig>(route.path()).await {
+        Ok(bytes) => {
+            serde_json::from_slice::<serde_json::Value>(&bytes)?;
+            let bytes = bytes.to_vec();
+            task::spawn_blocking(move || write_atomic(&path, &bytes).map(|_| bytes)).await?
+        }
+        Err(e) => {
+            let upstream_unavailable = match e.downcast_ref::<StatusError>() {
+                Some(status_error) => status_error.status.is_server_error(),
+                None => true,
+            };
+            if upstream_unavailable && is_file(&path).await {
+                Ok(tokio::fs::read(&path).await?)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+async fn serve_download<ClientConfig>(state: &MirrorState<ClientConfig>, request: &Request<Body>, route: &Route) -> Result<Response<Body>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let (project, version, build, name) = match route {
+        Route::Download(project, version, build, name) => (project, version, *build, name),
+        _ => return Ok(not_found()),
+    };
+
+    let builds_route = Route::VersionBuilds(project.clone(), version.clone(), build);
+    let builds: ProjectVersionBuildsResponse = serde_json::from_slice(&cached_response(state, &builds_route).await?)?;
+    let application = builds.downloads.application;
+    if &application.name != name {
+        return Ok(not_found());
+    }
+
+    let object = state.store.object_path(&application.sha256)?;
+    if !is_file(&object).await {
+        BuildDownloadRequest::new(project.clone(), version.clone(), build, name.clone())
+            .to_path::<ClientConfig, _>(&object, true, Some(&application.sha256))
+            .await?;
+    }
+    serve_file(request, &object, "application/java-archive").await
+}
+
+/// What a [`mirror_sync`] run fetched, skipped and repaired.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncReport {
+    pub responses: usize,
+    pub builds_fetched: usize,
+    pub builds_skipped: usize,
+    pub downloaded: usize,
+    pub verified: usize,
+    pub repaired: usize,
+}
+
+/// The file a route is written to in a static mirror tree. Listings are written as `index.json`
+/// inside the directory of their path, downloads at their path. Listing paths have no extension
+/// and are also the parents of deeper routes, so the web server must fall back from `$uri` to
+/// `$uri/index.json` and serve it as `application/json`.
+pub fn static_path(out: &Path, route: &Route) -> PathBuf {
+    let mut path = out.to_path_buf();
+    for segment in route.path().split('/').filter(|segment| !segment.is_empty()) {
+        path.push(segment);
+    }
+    match route {
+        Route::Download(..) => path,
+        _ => path.join("index.json"),
+    }
+}
+
+async fn sync_response<ClientConfig>(out: &Path, route: &Route, report: &mut SyncReport) -> Result<Vec<u8>>
+    where ClientConfig: PaperClientConfig
+{
+    let bytes = get_bytes::<ClientConfig>(route.path()).await?;
+    write_atomic(static_path(out, route), &bytes)?;
+    report.responses += 1;
+    Ok(bytes.to_vec())
+}
+
+/// Replicates `project` into a static directory tree a web server can host at the same paths
+/// as the API, see [`static_path`] for the configuration it needs. Build responses are only
+/// fetched once, downloads are re-verified against their sha256 on every run and fetched again
+/// when missing or mismatched.
+pub async fn mirror_sync<ClientConfig>(project: &str, versions: &VersionSpec, out: &Path) -> Result<SyncReport>
+    where ClientConfig: PaperClientConfig
+{
+    let mut report = SyncReport::default();
+    let project = project.to_string();
+
+    sync_response::<ClientConfig>(out, &Route::Projects, &mut report).await?;
+    let project_info: ProjectResponse = serde_json::from_slice(
+        &sync_response::<ClientConfig>(out, &Route::Project(project.clone()), &mut report).await?
+    )?;
+    let selected = versions.select(&project_info.versions);
+
+    for group in &project_info.version_groups {
+        let group_route = Route::GroupInfo(project.clone(), group.clone());
+        let group_info: ProjectGroupInfoResponse = serde_json::from_slice(&get_bytes::<ClientConfig>(group_route.path()).await?)?;
+        if group_info.versions.iter().any(|version| selected.contains(&version)) {
+            sync_response::<ClientConfig>(out, &group_route, &mut report).await?;
+            sync_response::<ClientConfig>(out, &Route::GroupBuilds(project.clone(), group.clone()), &mut report).await?;
+        }
+    }
+
+    for version in selected {
+        let version_info: ProjectVersionInfoResponse = serde_json::from_slice(
+            &sync_response::<ClientConfig>(out, &Route::VersionInfo(project.clone(), version.clone()), &mut report).await?
+        )?;
+
+        for build in version_info.builds {
+            let build_route = Route::VersionBuilds(project.clone(), version.clone(), build);
+            let build_path = static_path(out, &build_route);
+            let bytes = if build_path.is_file() {
+                report.builds_skipped += 1;
+                std::fs::read(&build_path)?
+            } else {
+                report.builds_fetched += 1;
+                sync_response::<ClientConfig>(out, &build_route, &mut report).await?
+            };
+            let build_info: ProjectVersionBuildsResponse = serde_json::from_slice(&bytes)?;
+            let application = build_info.downloads.application;
+
+            let download_route = Route::Download(project.clone(), version.clone(), build, application.name.clone());
+            download_route.validate()?;
+            let download_path = static_path(out, &download_route);
+            if download_path.is_file() {
+                if sha256_file(&download_path)?.eq_ignore_ascii_case(&application.sha256) {
+                    report.verified += 1;
+                    continue;
+                }
+                report.repaired += 1;
+            } else {
+                report.downloaded += 1;
+            }
+            BuildDownloadRequest::new(project.clone(), version.clone(), build, application.name.clone())
+                .to_path::<ClientConfig, _>(&download_path, true, Some(&application.sha256))
+                .await?;
+        }
+    }
+
+    Ok(report)
+}
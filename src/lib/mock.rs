@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::oneshot;
+
+use super::download::sha256_bytes;
+use super::server::{Route, error, json, not_found};
+use super::paper::*;
+use super::Result;
+
+/// The data served by a [`MockServer`], usually loaded from a JSON or TOML fixture file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MockFixture {
+    pub projects: Vec<MockProject>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockProject {
+    pub project_id: String,
+    pub project_name: String,
+    pub version_groups: Vec<MockVersionGroup>,
+    #[serde(default)]
+    pub builds: Vec<MockBuild>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockVersionGroup {
+    pub name: String,
+    pub versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockBuild {
+    pub version: String,
+    pub build: i32,
+    pub time: String,
+    #[serde(default)]
+    pub channel: BuildChannel,
+    #[serde(default)]
+    pub changes: Vec<ChangesInfo>,
+    pub download: MockDownload,
+}
+
+/// A download's content is either given inline or read from `file`, relative to the fixture.
+/// `sha256` overrides the computed hash, which allows serving deliberately mismatched hashes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockDownload {
+    pub name: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl MockFixture {
+    /// Reads a fixture, as TOML when the file ends in `.toml` and as JSON otherwise.
+    pub fn from_path<P>(path: P) -> Result<Self> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let is_toml = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+        let mut fixture: MockFixture = if is_toml {
+            toml::from_slice(&bytes)?
+        } else {
+            serde_json::from_slice(&bytes)?
+        };
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        for project in fixture.projects.iter_mut() {
+            for build in project.builds.iter_mut() {
+                if let Some(file) = build.download.file.take() {
+                    build.download.file = Some(base.join(file));
+                }
+            }
+        }
+        Ok(fixture)
+    }
+}
+
+/// Faults injected into the responses of a [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct MockOptions {
+    pub latency: Duration,
+    pub fail_every: Option<u64>,
+    pub error_status: u16,
+    pub truncate_downloads: bool,
+}
+
+impl Default for MockOptions {
+    fn default() -> Self {
+        Self { latency: Duration::from_millis(0), fail_every: None, error_status: 500, truncate_downloads: false }
+    }
+}
+
+type DownloadKey = (String, String, i32, String);
+
+struct MockState {
+    fixture: MockFixture,
+    options: MockOptions,
+    downloads: HashMap<DownloadKey, (Vec<u8>, String)>,
+    requests: AtomicU64,
+}
+
+/// A local HTTP server implementing the v2 routes of the Paper API. It stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    pub async fn start(fixture: MockFixture, options: MockOptions, addr: SocketAddr) -> Result<Self> {
+        let mut downloads = HashMap::new();
+        for project in &fixture.projects {
+            for build in &project.builds {
+                let bytes = match &build.download.file {
+                    Some(file) => std::fs::read(file)?,
+                    None => build.download.content.clone().into_bytes(),
+                };
+                let sha256 = build.download.sha256.clone().unwrap_or_else(|| sha256_bytes(&bytes));
+                let key = (project.project_id.clone(), build.version.clone(), build.build, build.download.name.clone());
+                downloads.insert(key, (bytes, sha256));
+            }
+        }
+
+        let state = Arc::new(MockState { fixture, options, downloads, requests: AtomicU64::new(0) });
+        let make_service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+            }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, receiver) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            receiver.await.ok();
+        }));
+
+        Ok(Self { addr, shutdown: Some(shutdown) })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The base url to point a client at, equivalent to [`super::BASE_URL`].
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(state: Arc<MockState>, request: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+    let count = state.requests.fetch_add(1, Ordering::SeqCst) + 1;
+    if state.options.latency > Duration::from_millis(0) {
+        tokio::time::delay_for(state.options.latency).await;
+    }
+    if let Some(every) = state.options.fail_every {
+        if every > 0 && count % every == 0 {
+            let status = StatusCode::from_u16(state.options.error_status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return Ok(error(status, "Injected failure."));
+        }
+    }
+    if request.method() != Method::GET {
+        return Ok(error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed."));
+    }
+
+    let route = match Route::parse(request.uri().path()) {
+        Some(route) => route,
+        None => return Ok(not_found()),
+    };
+
+    let response = match &route {
+        Route::Projects => json(&ProjectsResponse {
+            projects: state.fixture.projects.iter().map(|project| project.project_id.clone()).collect(),
+        }),
+        Route::Project(project) => match find_project(&state, project) {
+            Some(project) => json(&ProjectResponse {
+                project_id: project.project_id.clone(),
+                project_name: project.project_name.clone(),
+                version_groups: project.version_groups.iter().map(|group| group.name.clone()).collect(),
+                versions: versions(project),
+            }),
+            None => not_found(),
+        },
+        Route::GroupInfo(project, group) => match find_group(&state, project, group) {
+            Some((project, group)) => json(&ProjectGroupInfoResponse {
+                project_id: project.project_id.clone(),
+                project_name: project.project_name.clone(),
+                version_group: group.name.clone(),
+                versions: group.versions.clone(),
+            }),
+            None => not_found(),
+        },
+        Route::GroupBuilds(project, group) => match find_group(&state, project, group) {
+            Some((project, group)) => {
+                let mut builds: Vec<BuildInfo> = project.builds.iter()
+                    .filter(|build| group.versions.contains(&build.version))
+                    .map(|build| build_info(&state, project, build))
+                    .collect();
+                builds.sort_by_key(|build| build.build);
+                json(&ProjectGroupBuildsResponse {
+                    project_id: project.project_id.clone(),
+                    project_name: project.project_name.clone(),
+                    version_group: group.name.clone(),
+                    versions: group.versions.clone(),
+                    builds,
+                })
+            }
+            None => not_found(),
+        },
+        Route::VersionInfo(project, version) => match find_project(&state, project) {
+            Some(project) if versions(project).iter().any(|v| v == version) => {
+                let mut builds: Vec<i32> = project.builds.iter()
+                    .filter(|build| &build.version == version)
+                    .map(|build| build.build)
+                    .collect();
+                builds.sort_unstable();
+                json(&ProjectVersionInfoResponse {
+                    project_id: project.project_id.clone(),
+                    project_name: project.project_name.clone(),
+                    version: version.clone(),
+                    builds,
+                })
+            }
+            _ => not_found(),
+        },
+        Route::VersionBuilds(project, version, build) => match find_build(&state, project, version, *build) {
+            Some((project, build)) => {
+                let info = build_info(&state, project, build);
+                json(&ProjectVersionBuildsResponse {
+                    project_id: project.project_id.clone(),
+                    project_name: project.project_name.clone(),
+                    version: info.version,
+                    build: info.build,
+                    time: info.time,
+                    channel: info.channel,
+                    changes: info.changes,
+                    downloads: info.downloads,
+                })
+            }
+            None => not_found(),
+        },
+        Route::Download(project, version, build, name) => {
+            match find_build(&state, project, version, *build) {
+                Some((project, build)) if &build.download.name == name => {
+                    let key = (project.project_id.clone(), build.version.clone(), build.build, build.download.name.clone());
+                    let (bytes, _) = &state.downloads[&key];
+                    let body = if state.options.truncate_downloads {
+                        // Declares the full length but closes the connection halfway through, so
+                        // clients see a transfer ending early rather than a short, complete one.
+                        let (mut sender, body) = Body::channel();
+                        let half = bytes[..bytes.len() / 2].to_vec();
+                        tokio::spawn(async move {
+                            if sender.send_data(half.into()).await.is_ok() {
+                                sender.abort();
+                            }
+                        });
+                        body
+                    } else {
+                        Body::from(bytes.clone())
+                    };
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/java-archive")
+                        .header(CONTENT_LENGTH, bytes.len())
+                        .body(body)
+                        .expect("Response must be valid.")
+                }
+                _ => not_found(),
+            }
+        }
+    };
+    Ok(response)
+}
+
+fn find_project<'a>(state: &'a MockState, project: &str) -> Option<&'a MockProject> {
+    state.fixture.projects.iter().find(|p| p.project_id == project)
+}
+
+fn find_group<'a>(state: &'a MockState, project: &str, group: &str) -> Option<(&'a MockProject, &'a MockVersionGroup)> {
+    let project = find_project(state, project)?;
+    project.version_groups.iter().find(|g| g.name == group).map(|group| (project, group))
+}
+
+fn find_build<'a>(state: &'a MockState, project: &str, version: &str, build: i32) -> Option<(&'a MockProject, &'a MockBuild)> {
+    let project = find_project(state, project)?;
+    project.builds.iter().find(|b| b.version == version && b.build == build).map(|build| (project, build))
+}
+
+fn versions(project: &MockProject) -> Vec<String> {
+    let mut versions: Vec<String> = Vec::new();
+    for group in &project.version_groups {
+        for version in &group.versions {
+            if !versions.contains(version) {
+                versions.push(version.clone());
+            }
+        }
+    }
+    versions
+}
+
+fn build_info(state: &MockState, project: &MockProject, build: &MockBuild) -> BuildInfo {
+    let key = (project.project_id.clone(), build.version.clone(), build.build, build.download.name.clone());
+    let (_, sha256) = &state.downloads[&key];
+    BuildInfo {
+        build: build.build,
+        time: build.time.clone(),
+        channel: build.channel,
+        version: build.version.clone(),
+        changes: build.changes.clone(),
+        downloads: DownloadInfo {
+            application: ApplicationInfo { name: build.download.name.clone(), sha256: sha256.clone() },
+        },
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use super::feed::escape_xml;
+use super::paper::{BuildInfo, ChangesInfo};
+use super::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotesFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for NotesFormat {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "markdown" | "md" => Ok(NotesFormat::Markdown),
+            "html" => Ok(NotesFormat::Html),
+            _ => Err(Box::from(format!("Unknown notes format \"{}\".", format))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotesOptions {
+    pub format: NotesFormat,
+    /// The repository commits and `#1234` references link into, e.g. `https://github.com/PaperMC/Paper`.
+    pub repository: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Default for NotesOptions {
+    fn default() -> Self {
+        Self { format: NotesFormat::Markdown, repository: None, title: None }
+    }
+}
+
+impl NotesOptions {
+    fn commit_url(&self, commit: &str) -> Option<String> {
+        self.repository.as_ref().map(|repository| format!("{}/commit/{}", repository.trim_end_matches('/'), commit))
+    }
+
+    fn issue_url(&self, number: &str) -> Option<String> {
+        self.repository.as_ref().map(|repository| format!("{}/issues/{}", repository.trim_end_matches('/'), number))
+    }
+}
+
+/// Splits `text` around `#1234` references, which must follow whitespace, `(` or the start.
+fn split_references(text: &str) -> Vec<(&str, bool)> {
+    let mut parts = Vec::new();
+    let mut last = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let boundary = i == 0 || bytes[i - 1].is_ascii_whitespace() || bytes[i - 1] == b'(';
+        if bytes[i] == b'#' && boundary {
+            let digits = bytes[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+            let end = i + 1 + digits;
+            let terminated = end == bytes.len() || !bytes[end].is_ascii_alphanumeric();
+            if digits > 0 && terminated {
+                if last < i {
+                    parts.push((&text[last..i], false));
+                }
+                parts.push((&text[i..end], true));
+                last = end;
+                i = end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if last < text.len() {
+        parts.push((&text[last..], false));
+    }
+    parts
+}
+
+fn render_text(text: &str, options: &NotesOptions) -> String {
+    let mut rendered = String::new();
+    for (part, reference) in split_references(text) {
+        let url = if reference { options.issue_url(&part[1..]) } else { None };
+        match (options.format, url) {
+            (NotesFormat::Markdown, Some(url)) => { let _ = write!(rendered, "[{}]({})", part, url); }
+            (NotesFormat::Markdown, None) => rendered.push_str(part),
+            (NotesFormat::Html, Some(url)) => { let _ = write!(rendered, "<a href=\"{}\">{}</a>", escape_xml(&url), escape_xml(part)); }
+            (NotesFormat::Html, None) => rendered.push_str(&escape_xml(part)),
+        }
+    }
+    rendered
+}
+
+fn render_change(change: &ChangesInfo, options: &NotesOptions) -> String {
+    let short: String = change.commit.chars().take(7).collect();
+    let summary = render_text(&change.summary, options);
+    match (options.format, options.commit_url(&change.commit)) {
+        (NotesFormat::Markdown, Some(url)) => format!("- [`{}`]({}) {}", short, url, summary),
+        (NotesFormat::Markdown, None) => format!("- `{}` {}", short, summary),
+        (NotesFormat::Html, Some(url)) => format!("<li><a href=\"{}\"><code>{}</code></a> {}</li>", escape_xml(&url), escape_xml(&short), summary),
+        (NotesFormat::Html, None) => format!("<li><code>{}</code> {}</li>", escape_xml(&short), summary),
+    }
+}
+
+/// Renders the changes of `builds` grouped by version, newest version and build first.
+pub fn render_notes(builds: &[BuildInfo], options: &NotesOptions) -> String {
+    let mut sorted: Vec<&BuildInfo> = builds.iter().collect();
+    sorted.sort_by(|a, b| b.time.cmp(&a.time).then(b.build.cmp(&a.build)));
+    let mut versions: Vec<&str> = Vec::new();
+    for build in &sorted {
+        if !versions.contains(&build.version.as_str()) {
+            versions.push(&build.version);
+        }
+    }
+
+    let mut notes = String::new();
+    let markdown = options.format == NotesFormat::Markdown;
+    if let Some(title) = &options.title {
+        if markdown {
+            let _ = writeln!(notes, "# {}\n", title);
+        } else {
+            let _ = writeln!(notes, "<h1>{}</h1>", escape_xml(title));
+        }
+    }
+
+    for version in versions {
+        if markdown {
+            let _ = writeln!(notes, "## {}\n", version);
+        } else {
+            let _ = writeln!(notes, "<h2>{}</h2>", escape_xml(version));
+        }
+        for build in sorted.iter().filter(|build| build.version == version) {
+            if markdown {
+                let _ = writeln!(notes, "### Build #{} ({})\n", build.build, build.time);
+            } else {
+                let _ = writeln!(notes, "<h3>Build #{} ({})</h3>", build.build, escape_xml(&build.time));
+            }
+            if build.changes.is_empty() {
+                notes.push_str(if markdown { "No changes.\n\n" } else { "<p>No changes.</p>\n" });
+                continue;
+            }
+            if !markdown {
+                notes.push_str("<ul>\n");
+            }
+            for change in &build.changes {
+                notes.push_str(&render_change(change, options));
+                notes.push('\n');
+            }
+            notes.push_str(if markdown { "\n" } else { "</ul>\n" });
+        }
+    }
+    notes
+}
@@ -1,25 +1,95 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWrite;
+use tokio::task;
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
 
 use super::call_request;
+use super::download::{AtomicFile, DownloadStream};
 use super::{Result, PaperClientConfig};
 
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'<').add(b'>').add(b'`').add(b'?').add(b'{').add(b'}')
+    .add(b'/').add(b'%').add(b'\\').add(b'^').add(b'|').add(b'[').add(b']');
+
 pub trait Request {
     type Response;
 
     fn build_request_url(&self) -> String;
+
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A value which is placed into a single path segment of a request url.
+pub trait Segment {
+    fn validate_segment(&self) -> Result<()>;
+
+    fn encode_segment(&self) -> String;
+}
+
+impl Segment for String {
+    fn validate_segment(&self) -> Result<()> {
+        validate_identifier(self)
+    }
+
+    fn encode_segment(&self) -> String {
+        utf8_percent_encode(self, PATH_SEGMENT).to_string()
+    }
+}
+
+impl Segment for i32 {
+    fn validate_segment(&self) -> Result<()> {
+        if *self < 0 {
+            return Err(Box::from(format!("Invalid build number {}.", self)));
+        }
+        Ok(())
+    }
+
+    fn encode_segment(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Validates a project, version or version group identifier such as `paper` or `1.16.5`.
+pub fn validate_identifier(identifier: &str) -> Result<()> {
+    let valid_chars = identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' || c == '+');
+    if identifier.is_empty() || !valid_chars || identifier.contains("..") {
+        return Err(Box::from(format!("Invalid identifier \"{}\".", identifier)));
+    }
+    Ok(())
+}
+
+/// Validates a download name so it can be safely joined onto a local directory.
+pub fn validate_download_name(name: &str) -> Result<()> {
+    let invalid = name.is_empty()
+        || name.contains('/')
+        || name.contains('\\')
+        || name.contains('\0')
+        || name.contains("..")
+        || name.contains(':')
+        || name.starts_with('.');
+    if invalid {
+        return Err(Box::from(format!("Invalid download name \"{}\".", name)));
+    }
+    Ok(())
 }
 
 macro_rules! paper_struct {
     ($i:ident $($value:ident => $t:ty),+ $(,)?) => (
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, Clone)]
         pub struct $i { $(pub $value: $t,)+ }
     );
-    ($i:ident $($value:ident => $t:ty = $ext:ty),+ $(,)?) => (
-        #[derive(Serialize, Deserialize, Debug)]
-        pub struct $i { $(pub $value: $t,)+ }
+    ($i:ident $($(#[$attr:meta])* $value:ident => $t:ty = $ext:ty),+ $(,)?) => (
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        pub struct $i { $($(#[$attr])* pub $value: $t,)+ }
     );
     ($i:ident | $url:expr, $resp:ty) => (
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, Clone, Default)]
         pub struct $i;
         impl $i {
             pub fn new() -> Self { Self {} }
@@ -31,7 +101,7 @@ macro_rules! paper_struct {
         }
     );
     ($i:ident $($value:ident => $t:ty),+$(,)? | $url:expr, $resp:ty) => (
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, Clone)]
         pub struct $i { $(pub $value: $t,)+ }
 
         impl $i {
@@ -41,11 +111,12 @@ macro_rules! paper_struct {
 
         impl Request for $i {
             type Response = $resp;
-            fn build_request_url(&self) -> String { format!($url, $(self.$value),+) }
+            fn build_request_url(&self) -> String { format!($url, $(self.$value.encode_segment()),+) }
+            fn validate(&self) -> Result<()> { $(self.$value.validate_segment()?;)+ Ok(()) }
         }
     );
     ($i:ident $($value:ident => $t:ty = $ext:ty),+$(,)? | $url:expr, $resp:ty) => (
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, Clone)]
         pub struct $i { $(pub $value: $t,)+ }
 
         impl $i {
@@ -55,7 +126,8 @@ macro_rules! paper_struct {
 
         impl Request for $i {
             type Response = $resp;
-            fn build_request_url(&self) -> String { format!($url, $(self.$value),+) }
+            fn build_request_url(&self) -> String { format!($url, $(self.$value.encode_segment()),+) }
+            fn validate(&self) -> Result<()> { $(self.$value.validate_segment()?;)+ Ok(()) }
         }
     );
 }
@@ -64,14 +136,47 @@ paper_struct!(ChangesInfo commit => String, summary => String, message => String
 paper_struct!(ApplicationInfo name => String, sha256 => String);
 paper_struct!(DownloadInfo application => ApplicationInfo = ApplicationInfo);
 
+/// The release channel of a build. Responses without a channel are on the default one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildChannel {
+    #[default]
+    Default,
+    Experimental,
+}
+
 paper_struct! { BuildInfo
     build => i32 = i32,
     time => String = T,
+    #[serde(default)]
+    channel => BuildChannel = BuildChannel,
     version => String = T,
     changes => Vec<ChangesInfo> = Vec<ChangesInfo>,
     downloads => DownloadInfo = DownloadInfo,
 }
 
+impl BuildInfo {
+    pub fn parsed_time(&self) -> Result<DateTime<Utc>> {
+        parse_build_time(&self.time)
+    }
+}
+
+/// Parses the RFC 3339 timestamps used by the API, such as `2021-06-20T12:00:00.000Z`.
+pub fn parse_build_time(time: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc))
+}
+
+/// Parses a user supplied instant, either RFC 3339 or a date such as `2021-06-01`, which is
+/// taken as the start of that day in UTC.
+pub fn parse_instant(instant: &str) -> Result<DateTime<Utc>> {
+    let instant = instant.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(instant, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or("Invalid date.")?;
+        return Ok(DateTime::from_naive_utc_and_offset(midnight, Utc));
+    }
+    parse_build_time(instant).map_err(|_| Box::from(format!("Invalid date or time \"{}\".", instant)))
+}
+
 paper_struct!(ProjectsResponse projects => Vec<String> = Vec<String>);
 
 paper_struct!(ProjectsRequest | "/v2/projects", ProjectsResponse);
@@ -123,6 +228,8 @@ paper_struct! { ProjectVersionBuildsResponse
     version => String = T,
     build => i32 = i32,
     time => String = T,
+    #[serde(default)]
+    channel => BuildChannel = BuildChannel,
     changes => Vec<ChangesInfo> = Vec<ChangesInfo>,
     downloads => DownloadInfo = DownloadInfo,
 }
@@ -134,7 +241,26 @@ paper_struct! { ProjectVersionBuildsRequest
     | "/v2/projects/{}/versions/{}/builds/{}", ProjectVersionBuildsResponse
 }
 
-#[derive(Serialize, Deserialize)]
+impl ProjectVersionBuildsResponse {
+    pub fn parsed_time(&self) -> Result<DateTime<Utc>> {
+        parse_build_time(&self.time)
+    }
+}
+
+impl From<ProjectVersionBuildsResponse> for BuildInfo {
+    fn from(response: ProjectVersionBuildsResponse) -> Self {
+        Self {
+            build: response.build,
+            time: response.time,
+            channel: response.channel,
+            version: response.version,
+            changes: response.changes,
+            downloads: response.downloads,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BuildDownloadRequest {
     project: String,
     version: String,
@@ -150,13 +276,79 @@ impl BuildDownloadRequest {
     }
 
     pub fn build_request_url(&self) -> String {
-        format!("/v2/projects/{}/versions/{}/builds/{}/downloads/{}", self.project, self.version, self.build, self.download)
+        format!(
+            "/v2/projects/{}/versions/{}/builds/{}/downloads/{}",
+            self.project.encode_segment(),
+            self.version.encode_segment(),
+            self.build.encode_segment(),
+            self.download.encode_segment()
+        )
+    }
+
+    /// The absolute url of this download for `ClientConfig`.
+    pub fn download_url<ClientConfig>(&self) -> String where ClientConfig: PaperClientConfig {
+        super::build_url(&ClientConfig::base_url(), &self.build_request_url())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.project.validate_segment()?;
+        self.version.validate_segment()?;
+        self.build.validate_segment()?;
+        validate_download_name(&self.download)
     }
 
     pub async fn call<ClientConfig, Function>(&self, downloader: Function) -> super::Result<()>
         where ClientConfig: PaperClientConfig,
               Function: FnMut(&[u8]) + Sized
     {
+        self.validate()?;
         super::download_file::<ClientConfig, Function>(self.build_request_url(), downloader).await
     }
-}
\ No newline at end of file
+
+    /// Like `call`, but stops at the first error `downloader` returns.
+    pub async fn try_call<ClientConfig, Function>(&self, downloader: Function) -> super::Result<()>
+        where ClientConfig: PaperClientConfig,
+              Function: FnMut(&[u8]) -> super::Result<()> + Sized
+    {
+        self.validate()?;
+        super::try_download_file::<ClientConfig, Function>(self.build_request_url(), downloader).await
+    }
+
+    pub async fn stream<ClientConfig>(&self) -> Result<DownloadStream> where ClientConfig: PaperClientConfig {
+        self.validate()?;
+        super::download_stream::<ClientConfig>(self.build_request_url()).await
+    }
+
+    pub async fn copy_to<ClientConfig, W>(&self, writer: &mut W) -> Result<u64>
+        where ClientConfig: PaperClientConfig,
+              W: AsyncWrite + Unpin
+    {
+        self.stream::<ClientConfig>().await?.copy_to(writer).await
+    }
+
+    /// Downloads into `path` atomically, returning the sha256 of the written file.
+    pub async fn to_path<ClientConfig, P>(&self, path: P, create_dirs: bool, expected_sha256: Option<&str>) -> Result<String>
+        where ClientConfig: PaperClientConfig,
+              P: AsRef<Path>
+    {
+        // File writes and the final fsync run on the blocking pool, so a slow disk holds back
+        // the download instead of the runtime.
+        let path = path.as_ref().to_path_buf();
+        let file = task::spawn_blocking(move || AtomicFile::create(path, create_dirs)).await??;
+        let file = Arc::new(Mutex::new(file));
+        self.stream::<ClientConfig>().await?
+            .for_each_chunk(ClientConfig::debug(), |bytes| {
+                let file = file.clone();
+                async move {
+                    task::spawn_blocking(move || file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).write(&bytes)).await?
+                }
+            })
+            .await?;
+
+        let file = Arc::try_unwrap(file).map_err(|_| "The download file is still in use.")?
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let expected_sha256 = expected_sha256.map(String::from);
+        task::spawn_blocking(move || file.finish(expected_sha256.as_deref())).await?
+    }
+}
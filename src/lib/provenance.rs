@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use super::download::{sha256_file, write_atomic};
+#[cfg(feature = "index")]
+use super::index::BuildIndex;
+use super::paper::{BuildDownloadRequest, ProjectVersionBuildsResponse};
+use super::{PaperClientConfig, Result};
+
+/// Appended to a jar's file name for its sidecar, e.g. `server.jar.paper.json`.
+pub const SIDECAR_SUFFIX: &str = ".paper.json";
+
+/// Where a jar on disk came from, kept next to it as `<jar>.paper.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    /// The key of the download within the build, e.g. `application`.
+    pub download: String,
+    pub name: String,
+    pub source_url: String,
+    pub sha256: String,
+    pub build_time: String,
+    pub downloaded_at: String,
+    pub client_version: String,
+}
+
+impl Provenance {
+    /// Describes the application download of `response`, downloaded now.
+    pub fn from_response<ClientConfig>(response: &ProjectVersionBuildsResponse) -> Self
+        where ClientConfig: PaperClientConfig
+    {
+        let application = &response.downloads.application;
+        Self {
+            project: response.project_id.clone(),
+            version: response.version.clone(),
+            build: response.build,
+            download: String::from("application"),
+            name: application.name.clone(),
+            source_url: BuildDownloadRequest::new(response.project_id.as_str(), response.version.as_str(), response.build, application.name.as_str())
+                .download_url::<ClientConfig>(),
+            sha256: application.sha256.to_ascii_lowercase(),
+            build_time: response.time.clone(),
+            downloaded_at: Utc::now().to_rfc3339(),
+            client_version: format!("paper_api/{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    pub fn sidecar_path<P>(jar: P) -> PathBuf where P: AsRef<Path> {
+        let jar = jar.as_ref();
+        let mut name = jar.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+        name.push(SIDECAR_SUFFIX);
+        jar.with_file_name(name)
+    }
+
+    /// Reads the sidecar of `jar`, `None` when there is none.
+    pub fn read<P>(jar: P) -> Result<Option<Self>> where P: AsRef<Path> {
+        let path = Self::sidecar_path(jar);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn write<P>(&self, jar: P) -> Result<PathBuf> where P: AsRef<Path> {
+        let path = Self::sidecar_path(jar);
+        write_atomic(&path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Writes the sidecar of a freshly placed `jar` if `write` is set, otherwise removes its old
+    /// sidecar, which no longer describes the jar.
+    pub fn place<P>(&self, jar: P, write: bool) -> Result<()> where P: AsRef<Path> {
+        let jar = jar.as_ref();
+        if write {
+            self.write(jar)?;
+        } else if Self::sidecar_path(jar).exists() {
+            std::fs::remove_file(Self::sidecar_path(jar))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the sidecar of `jar` only if it still describes the jar, i.e. it records the jar's
+    /// `sha256`.
+    pub fn read_verified<P>(jar: P, sha256: &str) -> Result<Option<Self>> where P: AsRef<Path> {
+        Ok(Self::read(jar)?.filter(|provenance| provenance.sha256.eq_ignore_ascii_case(sha256)))
+    }
+}
+
+/// Downloads the application of `response` to `path`, verifying its hash, and optionally writes
+/// its provenance sidecar.
+pub async fn download_build<ClientConfig, P>(response: &ProjectVersionBuildsResponse, path: P, create_dirs: bool, write_provenance: bool) -> Result<Provenance>
+    where ClientConfig: PaperClientConfig,
+          P: AsRef<Path>
+{
+    let path = path.as_ref();
+    let application = &response.downloads.application;
+    let request = BuildDownloadRequest::new(response.project_id.as_str(), response.version.as_str(), response.build, application.name.as_str());
+    request.validate()?;
+    request.to_path::<ClientConfig, _>(path, create_dirs, Some(&application.sha256)).await?;
+
+    let provenance = Provenance::from_response::<ClientConfig>(response);
+    provenance.place(path, write_provenance)?;
+    Ok(provenance)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationSource {
+    Provenance,
+    HashLookup,
+}
+
+/// Whether a jar is an unmodified upstream build.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Verification {
+    pub jar: String,
+    pub sha256: String,
+    pub source: Option<VerificationSource>,
+    pub project: Option<String>,
+    pub version: Option<String>,
+    pub build: Option<i32>,
+    /// The hash the API publishes for the build.
+    pub expected_sha256: Option<String>,
+    pub verified: bool,
+    pub reason: Option<String>,
+}
+
+impl Verification {
+    fn unverified(jar: &Path) -> Result<Self> {
+        Ok(Self {
+            jar: jar.display().to_string(),
+            sha256: sha256_file(jar)?,
+            source: None,
+            project: None,
+            version: None,
+            build: None,
+            expected_sha256: None,
+            verified: false,
+            reason: None,
+        })
+    }
+
+    /// Checks the jar and its sidecar against the hash the API publishes for the sidecar's build.
+    async fn check_provenance<ClientConfig>(&mut self, provenance: &Provenance) -> Result<()>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let response = ClientConfig::get_version_builds(provenance.project.as_str(), provenance.version.as_str(), provenance.build).await?;
+        let expected = response.downloads.application.sha256.to_ascii_lowercase();
+        self.source = Some(VerificationSource::Provenance);
+        self.project = Some(provenance.project.clone());
+        self.version = Some(provenance.version.clone());
+        self.build = Some(provenance.build);
+        self.verified = expected.eq_ignore_ascii_case(&self.sha256) && provenance.sha256.eq_ignore_ascii_case(&self.sha256);
+        if !provenance.sha256.eq_ignore_ascii_case(&self.sha256) {
+            self.reason = Some(String::from("The jar changed since its sidecar was written."));
+        } else if !expected.eq_ignore_ascii_case(&self.sha256) {
+            self.reason = Some(String::from("The API publishes another hash for this build."));
+        }
+        self.expected_sha256 = Some(expected);
+        Ok(())
+    }
+
+    /// Marks the jar as the build whose published hash matched it.
+    fn found(&mut self, project: &str, version: &str, build: i32, expected_sha256: &str) {
+        self.source = Some(VerificationSource::HashLookup);
+        self.project = Some(project.to_string());
+        self.version = Some(version.to_string());
+        self.build = Some(build);
+        self.expected_sha256 = Some(expected_sha256.to_string());
+        self.verified = true;
+    }
+}
+
+/// Verifies `jar` against the API: through its sidecar when there is one, otherwise by looking
+/// its hash up in the builds of `project`.
+pub async fn verify_jar<ClientConfig, P>(project: &str, jar: P) -> Result<Verification>
+    where ClientConfig: PaperClientConfig + Send,
+          P: AsRef<Path>
+{
+    let jar = jar.as_ref();
+    let mut verification = Verification::unverified(jar)?;
+    if let Some(provenance) = Provenance::read(jar)? {
+        verification.check_provenance::<ClientConfig>(&provenance).await?;
+        return Ok(verification);
+    }
+
+    match super::identify::find_build_by_sha256::<ClientConfig>(project, &verification.sha256).await? {
+        Some(build) => verification.found(project, &build.version, build.build, &build.downloads.application.sha256),
+        None => verification.reason = Some(format!("No sidecar and no {} build has this hash.", project)),
+    }
+    Ok(verification)
+}
+
+/// Like [`verify_jar`], but looks the hash up in the builds of every project in `index` instead
+/// of fetching the builds of one project.
+#[cfg(feature = "index")]
+pub async fn verify_jar_indexed<ClientConfig, P>(index: &BuildIndex, jar: P) -> Result<Verification>
+    where ClientConfig: PaperClientConfig + Send,
+          P: AsRef<Path>
+{
+    let jar = jar.as_ref();
+    let mut verification = Verification::unverified(jar)?;
+    if let Some(provenance) = Provenance::read(jar)? {
+        verification.check_provenance::<ClientConfig>(&provenance).await?;
+        return Ok(verification);
+    }
+
+    match index.find_by_sha256(&verification.sha256)? {
+        Some(download) => verification.found(&download.project, &download.version, download.build, &download.sha256),
+        None => verification.reason = Some(String::from("No sidecar and no indexed build has this hash.")),
+    }
+    Ok(verification)
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use super::paper::{BuildInfo, ProjectResponse};
+use super::version::{VersionSpec, group_of};
+use super::{PaperClientConfig, Result};
+
+/// The builds of a project's versions, read from the builds of their version group, which are
+/// fetched once per group. Builds missing from their group, such as those of versions outside
+/// any group, are fetched one by one.
+pub struct GroupBuilds<'a> {
+    project: &'a ProjectResponse,
+    groups: HashMap<String, Vec<BuildInfo>>,
+}
+
+impl<'a> GroupBuilds<'a> {
+    pub fn new(project: &'a ProjectResponse) -> Self {
+        Self { project, groups: HashMap::new() }
+    }
+
+    async fn grouped<ClientConfig>(&mut self, version: &str) -> Result<Vec<BuildInfo>>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let group = match group_of(version, &self.project.version_groups) {
+            Some(group) => group,
+            None => return Ok(Vec::new()),
+        };
+        if !self.groups.contains_key(group) {
+            let response = ClientConfig::get_group_builds(self.project.project_id.as_str(), group.as_str()).await?;
+            self.groups.insert(group.clone(), response.builds);
+        }
+        Ok(self.groups[group].iter().filter(|build| build.version == version).cloned().collect())
+    }
+
+    /// All builds of `version`.
+    pub async fn version<ClientConfig>(&mut self, version: &str) -> Result<Vec<BuildInfo>>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let builds = self.grouped::<ClientConfig>(version).await?;
+        if !builds.is_empty() {
+            return Ok(builds);
+        }
+        let info = ClientConfig::get_version_info(self.project.project_id.as_str(), version).await?;
+        self.builds::<ClientConfig>(version, &info.builds).await
+    }
+
+    /// The given `builds` of `version`, in the same order.
+    pub async fn builds<ClientConfig>(&mut self, version: &str, builds: &[i32]) -> Result<Vec<BuildInfo>>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let grouped = if builds.is_empty() { Vec::new() } else { self.grouped::<ClientConfig>(version).await? };
+        let mut infos = Vec::with_capacity(builds.len());
+        for build in builds {
+            match grouped.iter().find(|info| info.build == *build) {
+                Some(info) => infos.push(info.clone()),
+                None => infos.push(ClientConfig::get_version_builds(self.project.project_id.as_str(), version, *build).await?.into()),
+            }
+        }
+        Ok(infos)
+    }
+}
+
+/// The newest of `builds` released no later than `at`, ordered by time and then build number.
+pub fn latest_at(builds: &[BuildInfo], at: DateTime<Utc>) -> Result<Option<&BuildInfo>> {
+    let mut latest: Option<(DateTime<Utc>, &BuildInfo)> = None;
+    for build in builds {
+        let time = build.parsed_time()?;
+        if time > at {
+            continue;
+        }
+        if latest.map_or(true, |(latest_time, latest)| (time, build.build) > (latest_time, latest.build)) {
+            latest = Some((time, build));
+        }
+    }
+    Ok(latest.map(|(_, build)| build))
+}
+
+/// The build a server following `spec` would have installed at `at`: the newest build of the
+/// newest matching version that already had a build by then, read through [`GroupBuilds`].
+pub async fn resolve_at<ClientConfig>(project: &str, spec: &VersionSpec, at: DateTime<Utc>) -> Result<Option<BuildInfo>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project = ClientConfig::get_project(project).await?;
+    let mut group_builds = GroupBuilds::new(&project);
+
+    for version in project.versions.iter().rev().filter(|version| spec.matches(version)) {
+        let builds = group_builds.version::<ClientConfig>(version).await?;
+        if let Some(build) = latest_at(&builds, at)? {
+            return Ok(Some(build.clone()));
+        }
+    }
+    Ok(None)
+}
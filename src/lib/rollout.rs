@@ -0,0 +1,554 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc};
+use futures_util::future::join_all;
+use serde::{Serialize, Deserialize};
+
+use super::download::{sha256_file, write_atomic};
+use super::fleet::{InstalledBuild, identify_server};
+use super::identify::BuildCatalog;
+use super::java::{JavaStatus, check_java};
+use super::paper::{BuildChannel, BuildInfo, validate_download_name};
+use super::provenance::{Provenance, SIDECAR_SUFFIX, download_build};
+use super::store::{JarStore, LOCKFILE, LinkMode, Lockfile};
+use super::version::{VersionSpec, compare_versions};
+use super::{PaperClientConfig, Result};
+
+/// The directory inside a server directory previous jars are kept in.
+pub const BACKUP_DIR: &str = ".paper-backups";
+const BACKUP_MANIFEST: &str = "backups.json";
+const HOLD_FILE: &str = "hold.json";
+
+/// A daily UTC time range updates may start in, optionally limited to some weekdays such as
+/// `["sat", "sun"]`. Ranges ending before they start wrap past midnight.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaintenanceWindow {
+    #[serde(default)]
+    pub days: Vec<String>,
+    pub start: String,
+    pub end: String,
+}
+
+impl MaintenanceWindow {
+    pub fn contains(&self, at: DateTime<Utc>) -> Result<bool> {
+        let parse = |time: &str| NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| format!("Invalid maintenance window time \"{}\", expected HH:MM.", time));
+        let (start, end) = (parse(&self.start)?, parse(&self.end)?);
+        let time = at.time();
+        let (in_range, day) = if start <= end {
+            (time >= start && time < end, at.weekday())
+        } else if time >= start {
+            (true, at.weekday())
+        } else {
+            // Early morning of a window which started the day before.
+            (time < end, at.weekday().pred())
+        };
+        let day = day.to_string().to_lowercase();
+        let day_allowed = self.days.is_empty() || self.days.iter().any(|allowed| allowed.to_lowercase().starts_with(&day[..3]));
+        Ok(in_range && day_allowed)
+    }
+}
+
+/// Which builds a server may be updated to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UpdatePolicy {
+    /// The versions to follow, e.g. `1.16.x`. Default: latest
+    #[serde(default)]
+    pub versions: VersionSpec,
+    /// `default` only adopts default builds, `experimental` adopts any build.
+    #[serde(default)]
+    pub channel: BuildChannel,
+    /// How old a build must be before it is adopted.
+    #[serde(default)]
+    pub min_build_age_hours: u64,
+    #[serde(default)]
+    pub maintenance_window: Option<MaintenanceWindow>,
+}
+
+impl UpdatePolicy {
+    fn allows(&self, build: &BuildInfo, now: DateTime<Utc>) -> Result<bool> {
+        let channel = self.channel == BuildChannel::Experimental || build.channel == BuildChannel::Default;
+        let age = Duration::hours(self.min_build_age_hours as i64);
+        Ok(channel && build.parsed_time()? + age <= now)
+    }
+}
+
+fn default_project() -> String {
+    String::from("paper")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FleetServer {
+    pub name: String,
+    pub directory: PathBuf,
+    #[serde(default = "default_project")]
+    pub project: String,
+    /// The jar file name the server starts, e.g. `server.jar`. Default: the identified jar, or
+    /// `server.jar` when there is none.
+    #[serde(default)]
+    pub jar: Option<String>,
+    /// Overrides the fleet's policy for this server.
+    #[serde(default)]
+    pub policy: Option<UpdatePolicy>,
+    /// Overrides the fleet's java executable for this server.
+    #[serde(default)]
+    pub java: Option<String>,
+}
+
+fn default_batch_size() -> usize {
+    5
+}
+
+fn default_keep_backups() -> usize {
+    3
+}
+
+/// A declarative fleet: its servers, their update policy and how updates are rolled out.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FleetConfig {
+    pub servers: Vec<FleetServer>,
+    #[serde(default)]
+    pub policy: UpdatePolicy,
+    /// How many servers are updated at once.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Seconds to wait between batches.
+    #[serde(default)]
+    pub batch_delay_secs: u64,
+    /// How many previous jars each server keeps.
+    #[serde(default = "default_keep_backups")]
+    pub keep_backups: usize,
+    /// Whether updated jars get a `<jar>.paper.json` provenance sidecar.
+    #[serde(default)]
+    pub write_provenance: bool,
+    /// A jar store updates are downloaded into once and linked from, see [`JarStore`].
+    #[serde(default)]
+    pub store: Option<PathBuf>,
+    /// How jars are placed from the store. Default: auto
+    #[serde(default)]
+    pub link_mode: LinkMode,
+    /// The java executable servers run with. Updates to versions it is too old for are skipped;
+    /// unset, Java is not checked, as the servers may run elsewhere.
+    #[serde(default)]
+    pub java: Option<String>,
+}
+
+impl FleetConfig {
+    pub fn from_path<P>(path: P) -> Result<Self> where P: AsRef<Path> {
+        let config: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+        if config.batch_size == 0 {
+            return Err(Box::from("batch_size must be at least 1."));
+        }
+        Ok(config)
+    }
+
+    pub fn server(&self, name: &str) -> Option<&FleetServer> {
+        self.servers.iter().find(|server| server.name == name)
+    }
+
+    pub fn policy_of<'a>(&'a self, server: &'a FleetServer) -> &'a UpdatePolicy {
+        server.policy.as_ref().unwrap_or(&self.policy)
+    }
+
+    pub fn java_of<'a>(&'a self, server: &'a FleetServer) -> Option<&'a str> {
+        server.java.as_deref().or(self.java.as_deref())
+    }
+
+    pub fn jar_store(&self) -> Option<JarStore> {
+        self.store.as_ref().map(|root| JarStore::new(root).with_link_mode(self.link_mode))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    UpToDate,
+    Update { version: String, build: i32, download: String, sha256: String },
+    Skipped { reason: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerPlan {
+    pub server: String,
+    pub jar: PathBuf,
+    pub installed: Option<InstalledBuild>,
+    pub action: PlannedAction,
+}
+
+/// The newest build `policy` allows: of the newest matching version which has one, so `latest`
+/// falls back to older versions while the newest only has builds the channel excludes. The
+/// project must be loaded into `catalog`.
+pub fn target_build<'a>(catalog: &'a BuildCatalog, project: &str, policy: &UpdatePolicy, now: DateTime<Utc>) -> Result<Option<&'a BuildInfo>> {
+    let project_info = catalog.project(project).ok_or_else(|| format!("Project {} is not loaded.", project))?;
+    for version in project_info.versions.iter().rev().filter(|version| policy.versions.matches(version)) {
+        let mut target: Option<&BuildInfo> = None;
+        for build in catalog.builds(project, version) {
+            if policy.allows(build, now)? && target.map_or(true, |target| build.build > target.build) {
+                target = Some(build);
+            }
+        }
+        if target.is_some() {
+            return Ok(target);
+        }
+    }
+    Ok(None)
+}
+
+async fn plan_action(catalog: &BuildCatalog, server: &FleetServer, policy: &UpdatePolicy, java: Option<&str>, jar: &Path, installed: Option<&InstalledBuild>, now: DateTime<Utc>) -> Result<PlannedAction> {
+    if let Some(hold) = read_hold(jar)? {
+        return Ok(PlannedAction::Skipped { reason: format!("Held since the rollback at {}, release the server to update it again.", hold.created) });
+    }
+    if installed.is_none() && jar.exists() {
+        return Ok(PlannedAction::Skipped { reason: format!("{} is not a known {} build.", jar.display(), server.project) });
+    }
+    let target = match target_build(catalog, &server.project, policy, now)? {
+        Some(target) => target,
+        None => return Ok(PlannedAction::Skipped { reason: format!("No build matches {}.", policy.versions) }),
+    };
+    if let Some(installed) = installed {
+        if installed.project != server.project {
+            return Ok(PlannedAction::Skipped { reason: format!("The installed jar is a {} build.", installed.project) });
+        }
+        match compare_versions(&installed.version, &target.version) {
+            std::cmp::Ordering::Greater => {
+                return Ok(PlannedAction::Skipped { reason: format!("{} is newer than the policy allows.", installed.version) });
+            }
+            std::cmp::Ordering::Equal if installed.build >= target.build => return Ok(PlannedAction::UpToDate),
+            _ => {}
+        }
+    }
+    if let Some(window) = &policy.maintenance_window {
+        if !window.contains(now)? {
+            return Ok(PlannedAction::Skipped { reason: String::from("Outside of the maintenance window.") });
+        }
+    }
+    if let Some(java) = java {
+        match check_java(java, &server.project, &target.version).await {
+            Ok(Some(check)) if check.status == JavaStatus::TooOld => return Ok(PlannedAction::Skipped { reason: check.message() }),
+            Ok(_) => {}
+            Err(error) => return Ok(PlannedAction::Skipped { reason: error.to_string() }),
+        }
+    }
+    Ok(PlannedAction::Update {
+        version: target.version.clone(),
+        build: target.build,
+        download: target.downloads.application.name.clone(),
+        sha256: target.downloads.application.sha256.clone(),
+    })
+}
+
+/// Identifies every server of `config` and decides what applying the policies would do at `now`.
+pub async fn plan_fleet<ClientConfig>(config: &FleetConfig, now: DateTime<Utc>) -> Result<Vec<ServerPlan>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let mut catalog = BuildCatalog::new();
+    let mut plans = Vec::with_capacity(config.servers.len());
+    for server in &config.servers {
+        catalog.load::<ClientConfig>(&server.project).await?;
+        let (identified_jar, installed) = identify_server::<ClientConfig>(&mut catalog, &server.directory).await?;
+        let jar = match (&server.jar, identified_jar) {
+            (Some(name), _) => server.directory.join(name),
+            (None, Some(jar)) => jar,
+            (None, None) => server.directory.join("server.jar"),
+        };
+        // A missing jar is a fresh install, whatever version_history.json says.
+        let installed = installed.filter(|_| jar.exists());
+        let action = plan_action(&catalog, server, config.policy_of(server), config.java_of(server), &jar, installed.as_ref(), now).await?;
+        plans.push(ServerPlan { server: server.name.clone(), jar, installed, action });
+    }
+    Ok(plans)
+}
+
+/// A previous jar kept before an update.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Backup {
+    pub created: String,
+    pub jar: String,
+    pub file: String,
+    /// The backed up provenance sidecar of the jar, if it had one.
+    #[serde(default)]
+    pub provenance: Option<String>,
+    pub installed: Option<InstalledBuild>,
+    /// The `paper-lock.json` of the server directory, if it had one.
+    #[serde(default)]
+    pub lockfile: Option<Lockfile>,
+}
+
+/// Written by a rollback, so later applies leave the restored jar alone until the server is
+/// released with [`release_jar`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hold {
+    pub created: String,
+    pub installed: Option<InstalledBuild>,
+}
+
+fn parent_dir(jar: &Path) -> &Path {
+    jar.parent().unwrap_or_else(|| Path::new("."))
+}
+
+fn backup_dir(jar: &Path) -> PathBuf {
+    parent_dir(jar).join(BACKUP_DIR)
+}
+
+fn read_backups(dir: &Path) -> Result<Vec<Backup>> {
+    let manifest = dir.join(BACKUP_MANIFEST);
+    if !manifest.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(manifest)?)?)
+}
+
+fn write_backups(dir: &Path, backups: &[Backup]) -> Result<()> {
+    write_atomic(dir.join(BACKUP_MANIFEST), &serde_json::to_vec_pretty(backups)?)
+}
+
+fn read_hold(jar: &Path) -> Result<Option<Hold>> {
+    let path = backup_dir(jar).join(HOLD_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+}
+
+/// Copies the jar into the server's backup directory, keeping the newest `keep` backups.
+pub fn backup_jar(jar: &Path, installed: Option<&InstalledBuild>, keep: usize) -> Result<Option<Backup>> {
+    if !jar.exists() {
+        return Ok(None);
+    }
+    let dir = backup_dir(jar);
+    std::fs::create_dir_all(&dir)?;
+    let jar_name = jar.file_name().ok_or("The jar path has no file name.")?.to_string_lossy().into_owned();
+    let created = Utc::now();
+    let file = format!("{}-{}", created.format("%Y%m%dT%H%M%S%.3fZ"), jar_name);
+    let sidecar = Provenance::sidecar_path(jar);
+    let backup = Backup {
+        created: created.to_rfc3339(),
+        provenance: if sidecar.exists() { Some(format!("{}{}", file, SIDECAR_SUFFIX)) } else { None },
+        file,
+        jar: jar_name,
+        installed: installed.cloned(),
+        lockfile: Lockfile::read(parent_dir(jar))?,
+    };
+    std::fs::copy(jar, dir.join(&backup.file))?;
+    if let Some(provenance) = &backup.provenance {
+        std::fs::copy(&sidecar, dir.join(provenance))?;
+    }
+
+    let mut backups = read_backups(&dir)?;
+    backups.push(backup.clone());
+    while backups.len() > keep.max(1) {
+        let removed = backups.remove(0);
+        let _ = std::fs::remove_file(dir.join(&removed.file));
+        if let Some(provenance) = &removed.provenance {
+            let _ = std::fs::remove_file(dir.join(provenance));
+        }
+    }
+    write_backups(&dir, &backups)?;
+    Ok(Some(backup))
+}
+
+/// Restores the newest backup of a server's jar, its sidecar and its lockfile, removing it from
+/// the backups, and holds the server at the restored jar. A lockfile of a backup which predates lockfile backups is rewritten from the
+/// restored build, or removed when the build is unknown.
+pub fn rollback_jar(jar: &Path) -> Result<Backup> {
+    let dir = backup_dir(jar);
+    let mut backups = read_backups(&dir)?;
+    let backup = backups.pop().ok_or_else(|| format!("There are no backups in {}.", dir.display()))?;
+    let directory = parent_dir(jar);
+    let target = directory.join(&backup.jar);
+
+    write_atomic(&target, &std::fs::read(dir.join(&backup.file))?)?;
+    let sidecar = Provenance::sidecar_path(&target);
+    match &backup.provenance {
+        Some(provenance) => std::fs::rename(dir.join(provenance), &sidecar)?,
+        None if sidecar.exists() => std::fs::remove_file(&sidecar)?,
+        None => {}
+    }
+    match (&backup.lockfile, &backup.installed) {
+        (Some(lockfile), _) => {
+            lockfile.write(directory)?;
+        }
+        (None, _) if Lockfile::read(directory)?.is_none() => {}
+        (None, Some(installed)) => {
+            let lockfile = Lockfile {
+                project: installed.project.clone(),
+                version: installed.version.clone(),
+                build: installed.build,
+                sha256: sha256_file(&target)?,
+                jar: backup.jar.clone(),
+            };
+            lockfile.write(directory)?;
+        }
+        (None, None) => std::fs::remove_file(directory.join(LOCKFILE))?,
+    }
+    std::fs::remove_file(dir.join(&backup.file))?;
+    write_backups(&dir, &backups)?;
+    let hold = Hold { created: Utc::now().to_rfc3339(), installed: backup.installed.clone() };
+    write_atomic(dir.join(HOLD_FILE), &serde_json::to_vec_pretty(&hold)?)?;
+    Ok(backup)
+}
+
+/// Lifts the hold a rollback put on a server's jar, returning it, or `None` when it was not held.
+pub fn release_jar(jar: &Path) -> Result<Option<Hold>> {
+    let hold = read_hold(jar)?;
+    if hold.is_some() {
+        std::fs::remove_file(backup_dir(jar).join(HOLD_FILE))?;
+    }
+    Ok(hold)
+}
+
+/// Restores the last backup of the named server of `config`.
+pub async fn rollback_server<ClientConfig>(config: &FleetConfig, name: &str) -> Result<Backup>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let server = config.server(name).ok_or_else(|| format!("Unknown server \"{}\".", name))?;
+    let jar = match &server.jar {
+        Some(jar) => server.directory.join(jar),
+        None => {
+            let backups = read_backups(&server.directory.join(BACKUP_DIR))?;
+            let last = backups.last().ok_or_else(|| format!("There are no backups of {}.", name))?;
+            server.directory.join(&last.jar)
+        }
+    };
+    rollback_jar(&jar)
+}
+
+/// Lifts the hold of the named server of `config`, so the next apply updates it again.
+pub fn release_server(config: &FleetConfig, name: &str) -> Result<Option<Hold>> {
+    let server = config.server(name).ok_or_else(|| format!("Unknown server \"{}\".", name))?;
+    release_jar(&server.directory.join(server.jar.as_deref().unwrap_or("server.jar")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerOutcome {
+    pub server: String,
+    pub action: PlannedAction,
+    pub backup: Option<Backup>,
+    pub error: Option<String>,
+}
+
+/// Where an update is downloaded to before it replaces the jar, beside the jar so the final
+/// rename stays on one filesystem.
+fn staged_path(jar: &Path) -> Result<PathBuf> {
+    let file_name = jar.file_name().ok_or_else(|| format!("Path {} does not name a file.", jar.display()))?;
+    let mut name = std::ffi::OsString::from(".");
+    name.push(file_name);
+    name.push(format!(".{}.update", std::process::id()));
+    Ok(jar.with_file_name(name))
+}
+
+/// Downloads and verifies the planned build before backing up the jar it replaces, so a failed
+/// download leaves the jar and its backups as they were.
+async fn update_server<ClientConfig>(plan: &ServerPlan, project: &str, config: &FleetConfig) -> Result<Option<Backup>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    if let PlannedAction::Update { version, build, download, .. } = &plan.action {
+        validate_download_name(download)?;
+        let response = ClientConfig::get_version_builds(project, version.as_str(), *build).await?;
+        let (backup, provenance) = match config.jar_store() {
+            Some(store) => {
+                store.fetch::<ClientConfig>(&response).await?;
+                let backup = backup_jar(&plan.jar, plan.installed.as_ref(), config.keep_backups)?;
+                (backup, store.install::<ClientConfig, _>(&response, &plan.jar, false, config.write_provenance).await?.0)
+            }
+            None => {
+                let staged = staged_path(&plan.jar)?;
+                let provenance = download_build::<ClientConfig, _>(&response, &staged, false, false).await?;
+                let backup = match backup_jar(&plan.jar, plan.installed.as_ref(), config.keep_backups) {
+                    Ok(backup) => backup,
+                    Err(error) => {
+                        let _ = std::fs::remove_file(&staged);
+                        return Err(error);
+                    }
+                };
+                std::fs::rename(&staged, &plan.jar)?;
+                provenance.place(&plan.jar, config.write_provenance)?;
+                (backup, provenance)
+            }
+        };
+
+        let directory = parent_dir(&plan.jar);
+        if Lockfile::read(directory)?.is_some() {
+            let jar = plan.jar.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            Lockfile::from_provenance(&provenance, &jar).write(directory)?;
+        }
+        return Ok(backup);
+    }
+    Ok(None)
+}
+
+/// Applies `plans` in batches of `config.batch_size`, backing up every replaced jar. A batch with
+/// a failed update stops the rollout; the servers of later batches are reported as skipped, as
+/// are servers whose maintenance window closed before their batch started.
+pub async fn apply_fleet<ClientConfig>(config: &FleetConfig, plans: &[ServerPlan]) -> Result<Vec<ServerOutcome>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let mut outcomes: Vec<ServerOutcome> = plans.iter()
+        .filter(|plan| !matches!(plan.action, PlannedAction::Update { .. }))
+        .map(|plan| ServerOutcome { server: plan.server.clone(), action: plan.action.clone(), backup: None, error: None })
+        .collect();
+    let updates: Vec<&ServerPlan> = plans.iter().filter(|plan| matches!(plan.action, PlannedAction::Update { .. })).collect();
+
+    let mut failed = false;
+    for (index, batch) in updates.chunks(config.batch_size.max(1)).enumerate() {
+        if failed {
+            outcomes.extend(batch.iter().map(|plan| ServerOutcome {
+                server: plan.server.clone(),
+                action: PlannedAction::Skipped { reason: String::from("An earlier batch failed.") },
+                backup: None,
+                error: None,
+            }));
+            continue;
+        }
+        if index > 0 && config.batch_delay_secs > 0 {
+            tokio::time::delay_for(StdDuration::from_secs(config.batch_delay_secs)).await;
+        }
+
+        // Plans are made ahead of time and batches are delayed, so a batch may reach servers
+        // whose maintenance window already closed.
+        let now = Utc::now();
+        let mut runnable = Vec::with_capacity(batch.len());
+        for plan in batch {
+            let window = config.server(&plan.server).and_then(|server| config.policy_of(server).maintenance_window.as_ref());
+            match window {
+                Some(window) if !window.contains(now)? => outcomes.push(ServerOutcome {
+                    server: plan.server.clone(),
+                    action: PlannedAction::Skipped { reason: String::from("Outside of the maintenance window.") },
+                    backup: None,
+                    error: None,
+                }),
+                _ => runnable.push(*plan),
+            }
+        }
+
+        if let Some(store) = config.jar_store() {
+            // Fetches every build of the batch once up front so its servers only link it. A
+            // failed fetch is retried and reported by the servers' own updates.
+            for plan in &runnable {
+                if let PlannedAction::Update { version, build, .. } = &plan.action {
+                    let project = config.server(&plan.server).map(|server| server.project.as_str()).unwrap_or("paper");
+                    if let Ok(response) = ClientConfig::get_version_builds(project, version.as_str(), *build).await {
+                        let _ = store.fetch::<ClientConfig>(&response).await;
+                    }
+                }
+            }
+        }
+
+        let results = join_all(runnable.iter().map(|plan| {
+            let project = config.server(&plan.server).map(|server| server.project.as_str()).unwrap_or("paper");
+            update_server::<ClientConfig>(plan, project, config)
+        })).await;
+        for (plan, result) in runnable.iter().zip(results) {
+            let (backup, error) = match result {
+                Ok(backup) => (backup, None),
+                Err(error) => {
+                    failed = true;
+                    (None, Some(error.to_string()))
+                }
+            };
+            outcomes.push(ServerOutcome { server: plan.server.clone(), action: plan.action.clone(), backup, error });
+        }
+    }
+    Ok(outcomes)
+}
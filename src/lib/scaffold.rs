@@ -0,0 +1,309 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use super::download::write_atomic;
+use super::identify::BuildCatalog;
+use super::java::enforce_java;
+use super::paper::{BuildChannel, validate_download_name};
+use super::provenance::{Provenance, download_build};
+use super::rollout::{UpdatePolicy, target_build};
+use super::store::{JarStore, LOCKFILE, Lockfile};
+use super::version::VersionSpec;
+use super::{PaperClientConfig, Result};
+
+/// The tuned G1 flags from <https://mcflags.emc.gs>.
+const AIKAR_FLAGS: &[&str] = &[
+    "-XX:+UseG1GC",
+    "-XX:+ParallelRefProcEnabled",
+    "-XX:MaxGCPauseMillis=200",
+    "-XX:+UnlockExperimentalVMOptions",
+    "-XX:+DisableExplicitGC",
+    "-XX:+AlwaysPreTouch",
+    "-XX:G1HeapWastePercent=5",
+    "-XX:G1MixedGCCountTarget=4",
+    "-XX:G1MixedGCLiveThresholdPercent=90",
+    "-XX:G1RSetUpdatingPauseTimePercent=5",
+    "-XX:SurvivorRatio=32",
+    "-XX:+PerfDisableSharedMem",
+    "-XX:MaxTenuringThreshold=1",
+    "-Dusing.aikars.flags=https://mcflags.emc.gs",
+    "-Daikars.new.flags=true",
+];
+/// The flags of the preset which depend on whether the heap exceeds 12 GB.
+const AIKAR_SMALL_HEAP_FLAGS: &[&str] = &[
+    "-XX:G1NewSizePercent=30",
+    "-XX:G1MaxNewSizePercent=40",
+    "-XX:G1HeapRegionSize=8M",
+    "-XX:G1ReservePercent=20",
+    "-XX:InitiatingHeapOccupancyPercent=15",
+];
+const AIKAR_LARGE_HEAP_FLAGS: &[&str] = &[
+    "-XX:G1NewSizePercent=40",
+    "-XX:G1MaxNewSizePercent=50",
+    "-XX:G1HeapRegionSize=16M",
+    "-XX:G1ReservePercent=15",
+    "-XX:InitiatingHeapOccupancyPercent=20",
+];
+
+/// Proxies take no `nogui` argument and are not tuned by the GC presets, which target servers.
+const PROXY_PROJECTS: &[&str] = &["velocity", "waterfall", "travertine"];
+
+fn is_proxy(project: &str) -> bool {
+    PROXY_PROJECTS.contains(&project.to_ascii_lowercase().as_str())
+}
+
+/// The garbage collector flags start scripts are generated with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GcPreset {
+    /// Aikar's G1 flags, tuned to the heap size.
+    #[default]
+    Aikar,
+    None,
+}
+
+impl FromStr for GcPreset {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(preset: &str) -> Result<Self> {
+        match preset.to_ascii_lowercase().as_str() {
+            "aikar" => Ok(GcPreset::Aikar),
+            "none" => Ok(GcPreset::None),
+            _ => Err(Box::from(format!("Unknown GC preset \"{}\".", preset))),
+        }
+    }
+}
+
+impl GcPreset {
+    pub fn flags(&self, heap_mb: u64) -> Vec<String> {
+        match self {
+            GcPreset::Aikar => {
+                let sized = if heap_mb > 12 * 1024 { AIKAR_LARGE_HEAP_FLAGS } else { AIKAR_SMALL_HEAP_FLAGS };
+                AIKAR_FLAGS.iter().chain(sized).map(|flag| flag.to_string()).collect()
+            }
+            GcPreset::None => Vec::new(),
+        }
+    }
+}
+
+/// Parses a JVM memory size such as `4G`, `512M` or `2048` (megabytes) into megabytes.
+pub fn parse_memory(memory: &str) -> Result<u64> {
+    let memory = memory.trim();
+    let invalid = || format!("Invalid memory size \"{}\", expected e.g. 4G or 512M.", memory);
+    let (number, factor) = match memory.chars().last().map(|unit| unit.to_ascii_uppercase()) {
+        Some('G') => (&memory[..memory.len() - 1], 1024),
+        Some('M') => (&memory[..memory.len() - 1], 1),
+        _ => (memory, 1),
+    };
+    let megabytes = number.parse::<u64>().map_err(|_| invalid())? * factor;
+    if megabytes == 0 {
+        return Err(Box::from(invalid()));
+    }
+    Ok(megabytes)
+}
+
+/// How a server directory is scaffolded.
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    pub project: String,
+    pub versions: VersionSpec,
+    pub channel: BuildChannel,
+    /// The jar file name inside the directory.
+    pub jar: String,
+    /// The initial and maximum heap, e.g. `4G`.
+    pub min_memory: String,
+    pub max_memory: String,
+    pub gc_preset: GcPreset,
+    /// Extra JVM flags placed after the preset's.
+    pub jvm_flags: Vec<String>,
+    /// The java executable the scripts start the server with, checked against the version.
+    pub java: String,
+    /// Installs the jar even if `java` is too old for it.
+    pub ignore_java: bool,
+    /// Writes `eula.txt` agreeing to the Minecraft EULA.
+    pub accept_eula: bool,
+    /// Links the jar from a store instead of downloading it into the directory.
+    pub store: Option<JarStore>,
+    /// Overwrites the files of an already initialized directory.
+    pub force: bool,
+    /// The account the systemd unit runs the server as. Default: `$SUDO_USER`, else `$USER`
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            project: String::from("paper"),
+            versions: VersionSpec::Latest,
+            channel: BuildChannel::Default,
+            jar: String::from("server.jar"),
+            min_memory: String::from("2G"),
+            max_memory: String::from("2G"),
+            gc_preset: GcPreset::Aikar,
+            jvm_flags: Vec::new(),
+            java: String::from("java"),
+            ignore_java: false,
+            accept_eula: false,
+            store: None,
+            force: false,
+            user: default_user(),
+            group: None,
+        }
+    }
+}
+
+/// The user invoking `sudo`, otherwise the current user, so that a unit scaffolded as root does
+/// not run the server as root.
+pub fn default_user() -> Option<String> {
+    ["SUDO_USER", "USER"].iter()
+        .filter_map(|variable| std::env::var(variable).ok())
+        .find(|user| !user.is_empty())
+}
+
+impl InitOptions {
+    /// The java command line the start script runs, without the executable. Proxies get neither
+    /// the GC preset nor `nogui`.
+    pub fn jvm_arguments(&self) -> Result<Vec<String>> {
+        let (min, max) = (parse_memory(&self.min_memory)?, parse_memory(&self.max_memory)?);
+        if min > max {
+            return Err(Box::from(format!("The initial heap {} exceeds the maximum heap {}.", self.min_memory, self.max_memory)));
+        }
+        let proxy = is_proxy(&self.project);
+        let mut arguments = vec![format!("-Xms{}M", min), format!("-Xmx{}M", max)];
+        if !proxy {
+            arguments.extend(self.gc_preset.flags(max));
+        }
+        arguments.extend(self.jvm_flags.iter().cloned());
+        arguments.extend(vec![String::from("-jar"), self.jar.clone()]);
+        if !proxy {
+            arguments.push(String::from("nogui"));
+        }
+        Ok(arguments)
+    }
+}
+
+/// The files `init_server` wrote.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InitReport {
+    pub directory: PathBuf,
+    pub provenance: Provenance,
+    pub files: Vec<PathBuf>,
+    pub eula_accepted: bool,
+    pub warnings: Vec<String>,
+}
+
+fn shell_quote(argument: &str) -> String {
+    let plain = !argument.is_empty() && argument.chars().all(|c| c.is_ascii_alphanumeric() || "-_=+:,./@%".contains(c));
+    if plain {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+pub fn start_script(options: &InitOptions) -> Result<String> {
+    let command: Vec<String> = std::iter::once(options.java.clone())
+        .chain(options.jvm_arguments()?)
+        .map(|argument| shell_quote(&argument))
+        .collect();
+    Ok(format!("#!/bin/sh\ncd \"$(dirname \"$0\")\" || exit 1\nexec {}\n", command.join(" ")))
+}
+
+/// A systemd unit running the start script of the absolute `directory` as `user` and `group`,
+/// or as root without a user.
+pub fn systemd_unit(directory: &Path, description: &str, user: Option<&str>, group: Option<&str>) -> String {
+    let directory = directory.display().to_string();
+    let account: String = [("User", user), ("Group", group)].iter()
+        .filter_map(|(key, value)| value.map(|value| format!("{}={}\n", key, value)))
+        .collect();
+    format!("[Unit]\n\
+             Description={}\n\
+             After=network-online.target\n\
+             Wants=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             {}\
+             WorkingDirectory={}\n\
+             ExecStart=/bin/sh \"{}/start.sh\"\n\
+             Restart=on-failure\n\
+             RestartSec=10\n\
+             SuccessExitStatus=0 143\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            description, account, directory, directory)
+}
+
+fn write_file(path: &Path, contents: &[u8], files: &mut Vec<PathBuf>) -> Result<()> {
+    write_atomic(path, contents)?;
+    files.push(path.to_path_buf());
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Scaffolds a server in `directory`: resolves and downloads the newest build `options` allow
+/// with its provenance sidecar, pins it in `paper-lock.json` and writes `start.sh` and a systemd
+/// unit. `eula.txt` is only written when the EULA was accepted.
+pub async fn init_server<ClientConfig, P>(directory: P, options: &InitOptions) -> Result<InitReport>
+    where ClientConfig: PaperClientConfig + Send,
+          P: AsRef<Path>
+{
+    validate_download_name(&options.jar)?;
+    let start = start_script(options)?;
+    let directory = directory.as_ref();
+    if !options.force && directory.join(LOCKFILE).exists() {
+        return Err(Box::from(format!("{} is already initialized, pass force to overwrite it.", directory.display())));
+    }
+
+    let mut catalog = BuildCatalog::new();
+    catalog.load::<ClientConfig>(&options.project).await?;
+    let policy = UpdatePolicy { versions: options.versions.clone(), channel: options.channel, ..UpdatePolicy::default() };
+    let build = target_build(&catalog, &options.project, &policy, Utc::now())?
+        .ok_or_else(|| format!("No {} build matches {}.", options.project, options.versions))?;
+    let warnings: Vec<String> = enforce_java(&options.java, &options.project, &build.version, options.ignore_java).await?.into_iter().collect();
+    let response = ClientConfig::get_version_builds(options.project.as_str(), build.version.as_str(), build.build).await?;
+
+    // Only created once a build was found, so a failed init leaves no empty directory behind.
+    std::fs::create_dir_all(directory)?;
+    let directory = directory.canonicalize()?;
+
+    let jar = directory.join(&options.jar);
+    let provenance = match &options.store {
+        Some(store) => store.install::<ClientConfig, _>(&response, &jar, false, true).await?.0,
+        None => download_build::<ClientConfig, _>(&response, &jar, false, true).await?,
+    };
+    let mut files = vec![jar.clone(), Provenance::sidecar_path(&jar)];
+    files.push(Lockfile::from_provenance(&provenance, &options.jar).write(&directory)?);
+
+    let script = directory.join("start.sh");
+    write_file(&script, start.as_bytes(), &mut files)?;
+    make_executable(&script)?;
+
+    let name = directory.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| options.project.clone());
+    let description = format!("Minecraft server {} ({} {})", name, provenance.project, provenance.version);
+    write_file(&directory.join(format!("{}.service", name)), systemd_unit(&directory, &description, options.user.as_deref(), options.group.as_deref()).as_bytes(), &mut files)?;
+
+    if options.accept_eula {
+        let eula = format!("#By changing the setting below to TRUE you are indicating your agreement to our EULA (https://aka.ms/MinecraftEULA).\n#{}\neula=true\n",
+                           Utc::now().to_rfc2822());
+        write_file(&directory.join("eula.txt"), eula.as_bytes(), &mut files)?;
+    }
+
+    Ok(InitReport { directory, provenance, files, eula_accepted: options.accept_eula, warnings })
+}
@@ -0,0 +1,105 @@
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Serialize, Deserialize};
+
+use super::classify::{ChangeKind, classify};
+use super::paper::{BuildInfo, ChangesInfo};
+use super::{PaperClientConfig, Result};
+
+/// How changes are matched: every keyword must appear, or the regex must match, ignoring case.
+#[derive(Debug, Clone)]
+pub enum SearchPattern {
+    Keywords(Vec<String>),
+    Regex(Regex),
+}
+
+impl SearchPattern {
+    pub fn keywords(query: &str) -> Self {
+        SearchPattern::Keywords(query.split_whitespace().map(|keyword| keyword.to_lowercase()).collect())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self> {
+        Ok(SearchPattern::Regex(RegexBuilder::new(pattern).case_insensitive(true).build()?))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            SearchPattern::Keywords(keywords) => {
+                let text = text.to_lowercase();
+                keywords.iter().all(|keyword| text.contains(keyword.as_str()))
+            }
+            SearchPattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    pub fn matches_change(&self, change: &ChangesInfo) -> bool {
+        self.is_match(&change.summary) || self.is_match(&change.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub pattern: SearchPattern,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl SearchQuery {
+    pub fn new(pattern: SearchPattern) -> Self {
+        Self { pattern, since: None, until: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangeMatch {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    pub time: String,
+    pub commit: String,
+    pub summary: String,
+    pub kind: ChangeKind,
+    pub references: Vec<u32>,
+}
+
+/// The matching changes of `builds`, oldest build first, so the first match is the first
+/// build which contained it.
+pub fn search_builds(project: &str, builds: &[BuildInfo], query: &SearchQuery) -> Result<Vec<ChangeMatch>> {
+    let mut builds: Vec<(DateTime<Utc>, &BuildInfo)> = builds.iter()
+        .map(|build| build.parsed_time().map(|time| (time, build)))
+        .collect::<Result<_>>()?;
+    builds.retain(|(time, _)| query.since.map_or(true, |since| *time >= since) && query.until.map_or(true, |until| *time <= until));
+    builds.sort_by(|(a_time, a), (b_time, b)| a_time.cmp(b_time).then(a.build.cmp(&b.build)));
+
+    let mut matches = Vec::new();
+    for (_, build) in builds {
+        for change in build.changes.iter().filter(|change| query.pattern.matches_change(change)) {
+            let classified = classify(change);
+            matches.push(ChangeMatch {
+                project: project.to_string(),
+                version: build.version.clone(),
+                build: build.build,
+                time: build.time.clone(),
+                commit: change.commit.clone(),
+                summary: change.summary.clone(),
+                kind: classified.kind,
+                references: classified.references,
+            });
+        }
+    }
+    Ok(matches)
+}
+
+pub async fn search_group_changes<ClientConfig>(project: &str, group: &str, query: &SearchQuery) -> Result<Vec<ChangeMatch>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let response = ClientConfig::get_group_builds(project, group).await?;
+    search_builds(&response.project_id, &response.builds, query)
+}
+
+pub async fn search_version_changes<ClientConfig>(project: &str, version: &str, query: &SearchQuery) -> Result<Vec<ChangeMatch>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let builds = ClientConfig::get_builds_of_version(project, version).await?;
+    search_builds(project, &builds, query)
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use hyper::{Body, Request, Response, StatusCode};
+use hyper::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+
+use super::paper::*;
+use super::paper::Request as _;
+use super::Result;
+
+/// A v2 route of the Paper API, as served by the mock and mirror servers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    Projects,
+    Project(String),
+    GroupInfo(String, String),
+    GroupBuilds(String, String),
+    VersionInfo(String, String),
+    VersionBuilds(String, String, i32),
+    Download(String, String, i32, String),
+}
+
+impl Route {
+    pub fn parse(path: &str) -> Option<Route> {
+        let segments: Vec<String> = path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        let route = match segments.as_slice() {
+            ["v2", "projects"] => Route::Projects,
+            ["v2", "projects", project] => Route::Project(project.to_string()),
+            ["v2", "projects", project, "version_group", group] => Route::GroupInfo(project.to_string(), group.to_string()),
+            ["v2", "projects", project, "version_group", group, "builds"] => Route::GroupBuilds(project.to_string(), group.to_string()),
+            ["v2", "projects", project, "versions", version] => Route::VersionInfo(project.to_string(), version.to_string()),
+            ["v2", "projects", project, "versions", version, "builds", build] => {
+                Route::VersionBuilds(project.to_string(), version.to_string(), build.parse().ok()?)
+            }
+            ["v2", "projects", project, "versions", version, "builds", build, "downloads", name] => {
+                Route::Download(project.to_string(), version.to_string(), build.parse().ok()?, name.to_string())
+            }
+            _ => return None,
+        };
+        Some(route)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Route::Projects => ProjectsRequest::new().validate(),
+            Route::Project(project) => ProjectRequest::new(project.clone()).validate(),
+            Route::GroupInfo(project, group) => ProjectGroupInfoRequest::new(project.clone(), group.clone()).validate(),
+            Route::GroupBuilds(project, group) => ProjectGroupBuildsRequest::new(project.clone(), group.clone()).validate(),
+            Route::VersionInfo(project, version) => ProjectVersionInfoRequest::new(project.clone(), version.clone()).validate(),
+            Route::VersionBuilds(project, version, build) => ProjectVersionBuildsRequest::new(project.clone(), version.clone(), *build).validate(),
+            Route::Download(project, version, build, name) => BuildDownloadRequest::new(project.clone(), version.clone(), *build, name.clone()).validate(),
+        }
+    }
+
+    /// The encoded url path of this route, relative to the base url.
+    pub fn path(&self) -> String {
+        match self {
+            Route::Projects => ProjectsRequest::new().build_request_url(),
+            Route::Project(project) => ProjectRequest::new(project.clone()).build_request_url(),
+            Route::GroupInfo(project, group) => ProjectGroupInfoRequest::new(project.clone(), group.clone()).build_request_url(),
+            Route::GroupBuilds(project, group) => ProjectGroupBuildsRequest::new(project.clone(), group.clone()).build_request_url(),
+            Route::VersionInfo(project, version) => ProjectVersionInfoRequest::new(project.clone(), version.clone()).build_request_url(),
+            Route::VersionBuilds(project, version, build) => ProjectVersionBuildsRequest::new(project.clone(), version.clone(), *build).build_request_url(),
+            Route::Download(project, version, build, name) => BuildDownloadRequest::new(project.clone(), version.clone(), *build, name.clone()).build_request_url(),
+        }
+    }
+}
+
+pub fn json<T>(value: &T) -> Response<Body> where T: Serialize {
+    json_bytes(serde_json::to_vec(value).expect("Response must serialize."))
+}
+
+pub fn json_bytes<T>(bytes: T) -> Response<Body> where T: Into<Bytes> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(bytes.into()))
+        .expect("Response must be valid.")
+}
+
+pub fn error(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = json(&serde_json::json!({ "error": message }));
+    *response.status_mut() = status;
+    response
+}
+
+pub fn not_found() -> Response<Body> {
+    error(StatusCode::NOT_FOUND, "Not found.")
+}
+
+/// Parses a single `bytes=` range against a body of `length` bytes into an inclusive range.
+pub fn parse_range(header: &str, length: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || length == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_at(spec.find('-')?);
+    let end = &end[1..];
+    let (start, end) = if start.is_empty() {
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (length.saturating_sub(suffix), length - 1)
+    } else {
+        let start = start.parse::<u64>().ok()?;
+        let end = if end.is_empty() { length - 1 } else { end.parse::<u64>().ok()?.min(length - 1) };
+        (start, end)
+    };
+    if start > end || start >= length {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Streams a file, honouring a single byte range in the request.
+pub async fn serve_file<T>(request: &Request<T>, path: &Path, content_type: &str) -> Result<Response<Body>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let length = file.metadata().await?.len();
+    let range = request.headers().get(RANGE).and_then(|value| value.to_str().ok());
+
+    let builder = Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .header(ACCEPT_RANGES, "bytes");
+    let (builder, start, count) = match range {
+        Some(range) => match parse_range(range, length) {
+            Some((start, end)) => {
+                let builder = builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length));
+                (builder, start, end - start + 1)
+            }
+            None => {
+                return Ok(Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(CONTENT_RANGE, format!("bytes */{}", length))
+                    .body(Body::empty())?);
+            }
+        },
+        None => (builder, 0, length),
+    };
+
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    let chunks = futures_util::stream::try_unfold((file, count), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        let mut buffer = vec![0u8; remaining.min(64 * 1024) as usize];
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        }
+        buffer.truncate(read);
+        Ok(Some((Bytes::from(buffer), (file, remaining - read as u64))))
+    });
+
+    Ok(builder.header(CONTENT_LENGTH, count).body(Body::wrap_stream(chunks))?)
+}
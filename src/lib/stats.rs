@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Serialize, Deserialize};
+
+use super::paper::BuildInfo;
+use super::{PaperClientConfig, Result};
+
+/// The build cadence of one version.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionStats {
+    pub version: String,
+    pub builds: usize,
+    pub changes: usize,
+    pub changes_per_build: f64,
+    /// Builds per week between the first and last build, counting at least one week.
+    pub builds_per_week: f64,
+    /// The median time between consecutive builds, `None` with fewer than two builds.
+    pub median_gap_hours: Option<f64>,
+    pub first_build_time: String,
+    pub last_build: i32,
+    pub last_build_time: String,
+    pub last_build_age_hours: f64,
+}
+
+/// The builds and changes of one week, starting on Monday in UTC.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeriodStats {
+    pub week_start: String,
+    pub builds: usize,
+    pub changes: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatsReport {
+    pub project: String,
+    pub generated_at: String,
+    /// The versions in the order they first appear in the builds.
+    pub versions: Vec<VersionStats>,
+    /// The weeks with the most changes, most changed first.
+    pub most_changed_periods: Vec<PeriodStats>,
+}
+
+fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let middle = values.len() / 2;
+    Some(if values.len() % 2 == 0 { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] })
+}
+
+fn week_start(time: DateTime<Utc>) -> NaiveDate {
+    let date = time.date_naive();
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Computes the cadence of `builds` as of `now`, keeping the `top` most changed weeks.
+pub fn compute_stats(project: &str, builds: &[BuildInfo], now: DateTime<Utc>, top: usize) -> Result<StatsReport> {
+    let mut timed: Vec<(DateTime<Utc>, &BuildInfo)> = builds.iter()
+        .map(|build| build.parsed_time().map(|time| (time, build)))
+        .collect::<Result<_>>()?;
+    timed.sort_by(|(a_time, a), (b_time, b)| a_time.cmp(b_time).then(a.build.cmp(&b.build)));
+
+    let mut order: Vec<&str> = Vec::new();
+    for (_, build) in &timed {
+        if !order.contains(&build.version.as_str()) {
+            order.push(&build.version);
+        }
+    }
+
+    let mut versions = Vec::with_capacity(order.len());
+    for version in order {
+        let version_builds: Vec<&(DateTime<Utc>, &BuildInfo)> = timed.iter().filter(|(_, build)| build.version == version).collect();
+        let (first_time, _) = version_builds[0];
+        let (last_time, last) = version_builds[version_builds.len() - 1];
+        let changes: usize = version_builds.iter().map(|(_, build)| build.changes.len()).sum();
+        let mut gaps: Vec<f64> = version_builds.windows(2).map(|pair| hours(pair[1].0 - pair[0].0)).collect();
+        let weeks = (hours(*last_time - *first_time) / (24.0 * 7.0)).max(1.0);
+
+        versions.push(VersionStats {
+            version: version.to_string(),
+            builds: version_builds.len(),
+            changes,
+            changes_per_build: changes as f64 / version_builds.len() as f64,
+            builds_per_week: version_builds.len() as f64 / weeks,
+            median_gap_hours: median(&mut gaps),
+            first_build_time: first_time.to_rfc3339(),
+            last_build: last.build,
+            last_build_time: last_time.to_rfc3339(),
+            last_build_age_hours: hours(now - *last_time),
+        });
+    }
+
+    let mut periods: BTreeMap<NaiveDate, PeriodStats> = BTreeMap::new();
+    for (time, build) in &timed {
+        let week = week_start(*time);
+        let period = periods.entry(week).or_insert_with(|| PeriodStats { week_start: week.to_string(), builds: 0, changes: 0 });
+        period.builds += 1;
+        period.changes += build.changes.len();
+    }
+    let mut most_changed_periods: Vec<PeriodStats> = periods.into_values().collect();
+    most_changed_periods.sort_by(|a, b| b.changes.cmp(&a.changes).then(b.builds.cmp(&a.builds)).then(a.week_start.cmp(&b.week_start)));
+    most_changed_periods.truncate(top);
+
+    Ok(StatsReport { project: project.to_string(), generated_at: now.to_rfc3339(), versions, most_changed_periods })
+}
+
+pub async fn group_stats<ClientConfig>(project: &str, group: &str, top: usize) -> Result<StatsReport>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let response = ClientConfig::get_group_builds(project, group).await?;
+    compute_stats(&response.project_id, &response.builds, Utc::now(), top)
+}
+
+pub async fn version_stats<ClientConfig>(project: &str, version: &str, top: usize) -> Result<StatsReport>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let builds = ClientConfig::get_builds_of_version(project, version).await?;
+    compute_stats(project, &builds, Utc::now(), top)
+}
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use super::download::{object_path, sha256_file, write_atomic};
+use super::paper::ProjectVersionBuildsResponse;
+use super::provenance::{Provenance, download_build};
+use super::{PaperClientConfig, Result};
+
+/// The lockfile a server directory pins its jar with.
+pub const LOCKFILE: &str = "paper-lock.json";
+
+/// How a stored jar is placed into a server directory. `auto` tries a hardlink, then a reflink,
+/// then copies. Reflinks are made with GNU coreutils' `cp --reflink=always`, so they are only
+/// available on Linux with a GNU `cp` and a file system supporting them, such as Btrfs or XFS.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    #[default]
+    Auto,
+    Hardlink,
+    Reflink,
+    Copy,
+}
+
+impl FromStr for LinkMode {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode.to_ascii_lowercase().as_str() {
+            "auto" => Ok(LinkMode::Auto),
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "reflink" => Ok(LinkMode::Reflink),
+            "copy" => Ok(LinkMode::Copy),
+            _ => Err(Box::from(format!("Unknown link mode \"{}\".", mode))),
+        }
+    }
+}
+
+impl std::fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LinkMode::Auto => "auto",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Reflink => "reflink",
+            LinkMode::Copy => "copy",
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, target: &Path) -> Result<()> {
+    let status = std::process::Command::new("cp")
+        .arg("--reflink=always")
+        .arg("--")
+        .arg(source)
+        .arg(target)
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        let _ = std::fs::remove_file(target);
+        return Err(Box::from(format!("Could not reflink {}, which needs GNU cp and a file system supporting reflinks.", target.display())));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _target: &Path) -> Result<()> {
+    Err(Box::from("Reflinks are only supported on Linux."))
+}
+
+/// Places `source` at `target` with the first method of `mode` which works, replacing `target`
+/// atomically, and returns the method used.
+pub fn link_file(source: &Path, target: &Path, mode: LinkMode) -> Result<LinkMode> {
+    let file_name = target.file_name()
+        .ok_or_else(|| format!("Path {} does not name a file.", target.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.link", std::process::id()));
+    let temp = target.with_file_name(temp_name);
+
+    let methods: &[LinkMode] = match mode {
+        LinkMode::Auto => &[LinkMode::Hardlink, LinkMode::Reflink, LinkMode::Copy],
+        LinkMode::Hardlink => &[LinkMode::Hardlink],
+        LinkMode::Reflink => &[LinkMode::Reflink],
+        LinkMode::Copy => &[LinkMode::Copy],
+    };
+    let mut last_error = None;
+    for method in methods {
+        let _ = std::fs::remove_file(&temp);
+        let result = match method {
+            LinkMode::Hardlink => std::fs::hard_link(source, &temp).map_err(Box::from),
+            LinkMode::Reflink => reflink(source, &temp),
+            _ => std::fs::copy(source, &temp).map(|_| ()).map_err(Box::from),
+        };
+        match result {
+            Ok(()) => {
+                std::fs::rename(&temp, target)?;
+                return Ok(*method);
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+    let _ = std::fs::remove_file(&temp);
+    Err(last_error.unwrap_or_else(|| Box::from("No link method was tried.")))
+}
+
+/// A jar in the store with its provenance, if the store still has it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredJar {
+    pub sha256: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub provenance: Option<Provenance>,
+}
+
+/// What a garbage collection removed, or would remove on a dry run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GcReport {
+    pub kept: Vec<StoredJar>,
+    pub removed: Vec<StoredJar>,
+    pub freed_bytes: u64,
+}
+
+/// A content-addressed store of jars shared by many server directories, keyed by the sha256 the
+/// API publishes: `objects/<first two>/<sha256>` with a provenance sidecar each.
+#[derive(Debug, Clone)]
+pub struct JarStore {
+    root: PathBuf,
+    link_mode: LinkMode,
+}
+
+impl JarStore {
+    pub fn new<P>(root: P) -> Self where P: AsRef<Path> {
+        Self { root: root.as_ref().to_path_buf(), link_mode: LinkMode::Auto }
+    }
+
+    pub fn with_link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn object_path(&self, sha256: &str) -> Result<PathBuf> {
+        object_path(&self.root, sha256)
+    }
+
+    fn entry(&self, path: PathBuf) -> Result<StoredJar> {
+        let sha256 = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok(StoredJar { sha256, size: std::fs::metadata(&path)?.len(), provenance: Provenance::read(&path)?, path })
+    }
+
+    /// Every jar in the store, sorted by sha256.
+    pub fn entries(&self) -> Result<Vec<StoredJar>> {
+        let objects = self.root.join("objects");
+        if !objects.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for prefix in std::fs::read_dir(objects)? {
+            let prefix = prefix?.path();
+            if !prefix.is_dir() {
+                continue;
+            }
+            for object in std::fs::read_dir(prefix)? {
+                let path = object?.path();
+                let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                // Skips sidecars and partial downloads.
+                if name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+                    entries.push(self.entry(path)?);
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+        Ok(entries)
+    }
+
+    /// Downloads the application of `response` into the store unless an intact copy is already
+    /// there.
+    pub async fn fetch<ClientConfig>(&self, response: &ProjectVersionBuildsResponse) -> Result<StoredJar>
+        where ClientConfig: PaperClientConfig
+    {
+        let sha256 = &response.downloads.application.sha256;
+        let object = self.object_path(sha256)?;
+        if !object.exists() || !sha256_file(&object)?.eq_ignore_ascii_case(sha256) {
+            download_build::<ClientConfig, _>(response, &object, true, true).await?;
+        } else if Provenance::read(&object)?.is_none() {
+            Provenance::from_response::<ClientConfig>(response).write(&object)?;
+        }
+        self.entry(object)
+    }
+
+    /// Places the application of `response` at `path` from the store, fetching it first when
+    /// needed, and optionally writes its provenance sidecar. Returns the link method used.
+    pub async fn install<ClientConfig, P>(&self, response: &ProjectVersionBuildsResponse, path: P, create_dirs: bool, write_provenance: bool) -> Result<(Provenance, LinkMode)>
+        where ClientConfig: PaperClientConfig,
+              P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let stored = self.fetch::<ClientConfig>(response).await?;
+        if create_dirs {
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let method = link_file(&stored.path, path, self.link_mode)?;
+
+        let provenance = stored.provenance.unwrap_or_else(|| Provenance::from_response::<ClientConfig>(response));
+        provenance.place(path, write_provenance)?;
+        Ok((provenance, method))
+    }
+
+    /// Removes every jar except the newest `keep_builds` builds of each version and the jars in
+    /// `referenced`. Jars without provenance are always kept, as their version is unknown.
+    pub fn gc(&self, keep_builds: usize, referenced: &HashSet<String>, dry_run: bool) -> Result<GcReport> {
+        let mut versions: BTreeMap<(String, String), Vec<StoredJar>> = BTreeMap::new();
+        let mut report = GcReport::default();
+        for entry in self.entries()? {
+            match &entry.provenance {
+                Some(provenance) => versions.entry((provenance.project.clone(), provenance.version.clone())).or_default().push(entry),
+                None => report.kept.push(entry),
+            }
+        }
+
+        for (_, mut entries) in versions {
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.provenance.as_ref().map(|provenance| provenance.build)));
+            for (index, entry) in entries.into_iter().enumerate() {
+                if index < keep_builds || referenced.contains(&entry.sha256) {
+                    report.kept.push(entry);
+                    continue;
+                }
+                if !dry_run {
+                    std::fs::remove_file(&entry.path)?;
+                    let sidecar = Provenance::sidecar_path(&entry.path);
+                    if sidecar.exists() {
+                        std::fs::remove_file(sidecar)?;
+                    }
+                }
+                report.freed_bytes += entry.size;
+                report.removed.push(entry);
+            }
+        }
+        report.kept.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+        Ok(report)
+    }
+}
+
+/// The build a server directory is pinned to, kept in its `paper-lock.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Lockfile {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    pub sha256: String,
+    /// The jar file name inside the server directory.
+    pub jar: String,
+}
+
+impl Lockfile {
+    pub fn from_provenance(provenance: &Provenance, jar: &str) -> Self {
+        Self {
+            project: provenance.project.clone(),
+            version: provenance.version.clone(),
+            build: provenance.build,
+            sha256: provenance.sha256.to_ascii_lowercase(),
+            jar: jar.to_string(),
+        }
+    }
+
+    /// Reads the lockfile of a server directory, `None` when there is none.
+    pub fn read<P>(directory: P) -> Result<Option<Self>> where P: AsRef<Path> {
+        let path = directory.as_ref().join(LOCKFILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn write<P>(&self, directory: P) -> Result<PathBuf> where P: AsRef<Path> {
+        let path = directory.as_ref().join(LOCKFILE);
+        write_atomic(&path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// The lowercase sha256 of every jar pinned by the lockfiles of `directories`. Directories
+/// without a lockfile are skipped.
+pub fn locked_hashes(directories: &[PathBuf]) -> Result<HashSet<String>> {
+    let mut hashes = HashSet::new();
+    for directory in directories {
+        if let Some(lockfile) = Lockfile::read(directory)? {
+            hashes.insert(lockfile.sha256.to_ascii_lowercase());
+        }
+    }
+    Ok(hashes)
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use super::paper::{BuildInfo, ProjectResponse};
+use super::resolve::{GroupBuilds, latest_at};
+use super::version::group_of;
+use super::{PaperClientConfig, Result};
+
+/// When a version counts as unsupported: its newest build is older than `max_build_age`, or,
+/// with `superseded`, a newer version exists in the same or a later version group.
+#[derive(Debug, Clone)]
+pub struct SupportPolicy {
+    pub max_build_age: Duration,
+    pub superseded: bool,
+}
+
+impl Default for SupportPolicy {
+    fn default() -> Self {
+        Self { max_build_age: Duration::days(90), superseded: false }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UnsupportedReason {
+    Inactive { last_build_time: String, age_days: i64 },
+    NoBuilds,
+    Superseded { newest: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionSupport {
+    pub project: String,
+    pub version: String,
+    pub group: Option<String>,
+    pub last_build: Option<i32>,
+    pub last_build_time: Option<String>,
+    pub reasons: Vec<UnsupportedReason>,
+}
+
+impl VersionSupport {
+    pub fn is_supported(&self) -> bool {
+        self.reasons.is_empty()
+    }
+
+    pub fn marker(&self) -> &'static str {
+        if self.is_supported() { "supported" } else { "unsupported" }
+    }
+
+    /// A warning for servers running this version, `None` while it is supported.
+    pub fn warning(&self) -> Option<String> {
+        if self.is_supported() {
+            return None;
+        }
+        let reasons: Vec<String> = self.reasons.iter().map(|reason| match reason {
+            UnsupportedReason::Inactive { age_days, .. } => format!("its newest build is {} days old", age_days),
+            UnsupportedReason::NoBuilds => String::from("it has no builds"),
+            UnsupportedReason::Superseded { newest } => format!("{} is available", newest),
+        }).collect();
+        Some(format!("{} {} is unsupported: {}.", self.project, self.version, reasons.join(", ")))
+    }
+}
+
+/// Assesses `version` of `project` given its newest build, as of `now`.
+pub fn assess_version(project: &ProjectResponse, version: &str, last_build: Option<&BuildInfo>, now: DateTime<Utc>, policy: &SupportPolicy) -> Result<VersionSupport> {
+    let mut reasons = Vec::new();
+    match last_build {
+        Some(build) => {
+            let age = now - build.parsed_time()?;
+            if age > policy.max_build_age {
+                reasons.push(UnsupportedReason::Inactive { last_build_time: build.time.clone(), age_days: age.num_days() });
+            }
+        }
+        None => reasons.push(UnsupportedReason::NoBuilds),
+    }
+
+    let group = group_of(version, &project.version_groups);
+    if policy.superseded {
+        let group_position = |group: Option<&String>| group.and_then(|group| project.version_groups.iter().position(|g| g == group));
+        let own_group = group_position(group);
+        let newer = project.versions.iter()
+            .skip_while(|candidate| candidate.as_str() != version)
+            .skip(1)
+            .filter(|candidate| group_position(group_of(candidate, &project.version_groups)) >= own_group)
+            .last();
+        if let Some(newest) = newer {
+            reasons.push(UnsupportedReason::Superseded { newest: newest.clone() });
+        }
+    }
+
+    Ok(VersionSupport {
+        project: project.project_id.clone(),
+        version: version.to_string(),
+        group: group.cloned(),
+        last_build: last_build.map(|build| build.build),
+        last_build_time: last_build.map(|build| build.time.clone()),
+        reasons,
+    })
+}
+
+/// Assesses a single version, reading only its newest build.
+pub async fn version_support<ClientConfig>(project: &str, version: &str, policy: &SupportPolicy) -> Result<VersionSupport>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project_info = ClientConfig::get_project(project).await?;
+    let version_info = ClientConfig::get_version_info(project, version).await?;
+    let last_build: Option<BuildInfo> = match version_info.builds.iter().max() {
+        Some(build) => Some(ClientConfig::get_version_builds(project, version, *build).await?.into()),
+        None => None,
+    };
+    assess_version(&project_info, version, last_build.as_ref(), Utc::now(), policy)
+}
+
+/// Assesses every version of `project`, reading the builds of each version group once.
+pub async fn project_support<ClientConfig>(project: &str, policy: &SupportPolicy) -> Result<Vec<VersionSupport>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let project_info = ClientConfig::get_project(project).await?;
+    let now = Utc::now();
+    let mut group_builds = GroupBuilds::new(&project_info);
+    let mut support = Vec::with_capacity(project_info.versions.len());
+    for version in &project_info.versions {
+        let builds = group_builds.version::<ClientConfig>(version).await?;
+        support.push(assess_version(&project_info, version, latest_at(&builds, now)?, now, policy)?);
+    }
+    Ok(support)
+}
@@ -1,5 +1,1215 @@
+use std::cell::RefCell;
+
+use super::download::{AtomicFile, sha256_file};
+use super::mock::{MockFixture, MockOptions, MockServer};
+use super::paper::BuildDownloadRequest;
+use super::PaperClientConfig;
+
+thread_local!(static MOCK_URL: RefCell<String> = const { RefCell::new(String::new()) });
+thread_local!(static MIRROR_URL: RefCell<String> = const { RefCell::new(String::new()) });
+
+struct MockClient;
+
+impl PaperClientConfig for MockClient {
+    type ConfigType = Self;
+
+    fn debug() -> bool {
+        false
+    }
+
+    fn base_url() -> String {
+        MOCK_URL.with(|url| url.borrow().clone())
+    }
+}
+
+struct MirrorClient;
+
+impl PaperClientConfig for MirrorClient {
+    type ConfigType = Self;
+
+    fn debug() -> bool {
+        false
+    }
+
+    fn base_url() -> String {
+        MIRROR_URL.with(|url| url.borrow().clone())
+    }
+}
+
+fn mock_fixture() -> MockFixture {
+    serde_json::from_value(serde_json::json!({
+        "projects": [{
+            "project_id": "paper",
+            "project_name": "Paper",
+            "version_groups": [
+                { "name": "1.16", "versions": ["1.16.4", "1.16.5"] },
+                { "name": "1.17", "versions": ["1.17"] }
+            ],
+            "builds": [
+                {
+                    "version": "1.16.4", "build": 416, "time": "2020-12-23T01:00:00.000Z",
+                    "changes": [{ "commit": "aaaaaaa", "summary": "Updated Upstream (Bukkit/CraftBukkit/Spigot)", "message": "Updated Upstream (Bukkit/CraftBukkit/Spigot)" }],
+                    "download": { "name": "paper-1.16.4-416.jar", "content": "paper 416" }
+                },
+                {
+                    "version": "1.16.5", "build": 793, "time": "2021-06-20T12:00:00.000Z",
+                    "changes": [{ "commit": "bbbbbbb", "summary": "Fix chunk loading (#5926)", "message": "Fix chunk loading (#5926)" }],
+                    "download": { "name": "paper-1.16.5-793.jar", "content": "paper 793" }
+                },
+                {
+                    "version": "1.16.5", "build": 794, "time": "2021-07-01T12:00:00.000Z",
+                    "changes": [{ "commit": "ccccccc", "summary": "Add new API", "message": "Add new API" }],
+                    "download": { "name": "paper-1.16.5-794.jar", "content": "paper 794" }
+                },
+                {
+                    "version": "1.17", "build": 1, "time": "2021-07-02T12:00:00.000Z", "channel": "experimental",
+                    "download": { "name": "paper-1.17-1.jar", "content": "paper 1.17 1" }
+                }
+            ]
+        }]
+    })).unwrap()
+}
+
+async fn start_mock(options: MockOptions) -> MockServer {
+    let server = MockServer::start(mock_fixture(), options, ([127, 0, 0, 1], 0).into()).await.unwrap();
+    MOCK_URL.with(|url| *url.borrow_mut() = server.url());
+    server
+}
+
 #[test]
 fn sanity() {
     assert!(true);
     assert_eq!(2 + 2, 4);
 }
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("paper_api_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn atomic_file_writes_and_verifies() {
+    let dir = temp_dir("atomic");
+    let target = dir.join("nested").join("paper.jar");
+    let hash = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    assert!(AtomicFile::create(&target, false).is_err());
+
+    let mut file = AtomicFile::create(&target, true).unwrap();
+    file.write(b"pap").unwrap();
+    file.write(b"er").unwrap();
+    assert!(file.finish(Some(hash)).is_err());
+    assert!(!target.exists());
+    assert_eq!(std::fs::read_dir(target.parent().unwrap()).unwrap().count(), 0);
+
+    let mut file = AtomicFile::create(&target, false).unwrap();
+    file.write(b"paper").unwrap();
+    let actual = file.finish(None).unwrap();
+    assert_eq!(std::fs::read(&target).unwrap(), b"paper");
+    assert_eq!(sha256_file(&target).unwrap(), actual);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn request_urls_are_encoded() {
+    use super::paper::{Request, ProjectVersionBuildsRequest, ProjectRequest, BuildDownloadRequest};
+
+    let request = ProjectVersionBuildsRequest::new("paper", "1.16.5", 794);
+    assert_eq!(request.build_request_url(), "/v2/projects/paper/versions/1.16.5/builds/794");
+    assert!(request.validate().is_ok());
+
+    let request = ProjectRequest::new("../paper?x=1");
+    assert_eq!(request.build_request_url(), "/v2/projects/..%2Fpaper%3Fx=1");
+    assert!(request.validate().is_err());
+
+    assert!(BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar").validate().is_ok());
+    assert!(BuildDownloadRequest::new("paper", "1.16.5", 794, "../paper.jar").validate().is_err());
+    assert!(BuildDownloadRequest::new("paper", "1.16.5", 794, "sub/paper.jar").validate().is_err());
+}
+
+#[tokio::test]
+async fn download_stream_copies_body() {
+    use super::download::DownloadStream;
+
+    let response = hyper::Response::builder()
+        .header(hyper::header::CONTENT_LENGTH, "5")
+        .body(hyper::Body::from("paper"))
+        .unwrap();
+    let stream = DownloadStream::new(response);
+    assert_eq!(stream.content_length(), Some(5));
+
+    let mut output = Vec::new();
+    assert_eq!(stream.copy_to(&mut output).await.unwrap(), 5);
+    assert_eq!(output, b"paper");
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn blocking_client_rejects_invalid_requests() {
+    use super::blocking::BlockingClient;
+    use super::PaperClient;
+
+    let client = BlockingClient::<PaperClient>::new().unwrap();
+    assert!(client.get_project("../paper").is_err());
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn blocking_client_calls_the_mock_server() {
+    use super::blocking::BlockingClient;
+
+    // The mock server runs on worker threads while this thread blocks on the client.
+    let mut runtime = tokio::runtime::Builder::new().threaded_scheduler().enable_all().build().unwrap();
+    let server = runtime.block_on(MockServer::start(mock_fixture(), MockOptions::default(), ([127, 0, 0, 1], 0).into())).unwrap();
+    MOCK_URL.with(|url| *url.borrow_mut() = server.url());
+
+    let client = BlockingClient::<MockClient>::new().unwrap();
+    assert_eq!(client.get_project("paper").unwrap().versions, vec!["1.16.4", "1.16.5", "1.17"]);
+    let build = client.get_version_builds("paper", "1.16.5", 794).unwrap();
+    assert_eq!(build.downloads.application.name, "paper-1.16.5-794.jar");
+
+    let request = BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar");
+    let mut downloaded = Vec::new();
+    client.download(&request, |bytes| {
+        downloaded.extend_from_slice(bytes);
+        Ok(())
+    }).unwrap();
+    assert_eq!(downloaded, b"paper 794");
+    let mut copied = Vec::new();
+    assert_eq!(client.copy_to(&request, &mut copied).unwrap(), 9);
+    assert_eq!(copied, b"paper 794");
+
+    let dir = temp_dir("blocking");
+    let sha256 = client.to_path(&request, dir.join("paper.jar"), true, Some(&build.downloads.application.sha256)).unwrap();
+    assert_eq!(sha256, build.downloads.application.sha256);
+    assert_eq!(std::fs::read(dir.join("paper.jar")).unwrap(), b"paper 794");
+    std::fs::remove_dir_all(&dir).unwrap();
+    drop(server);
+}
+
+#[tokio::test]
+async fn mock_server_serves_all_routes() {
+    let _server = start_mock(MockOptions::default()).await;
+
+    assert_eq!(MockClient::get_projects().await.unwrap().projects, vec!["paper"]);
+
+    let project = MockClient::get_project("paper").await.unwrap();
+    assert_eq!(project.version_groups, vec!["1.16", "1.17"]);
+    assert_eq!(project.versions, vec!["1.16.4", "1.16.5", "1.17"]);
+
+    let group = MockClient::get_group_info("paper", "1.16").await.unwrap();
+    assert_eq!(group.versions, vec!["1.16.4", "1.16.5"]);
+
+    let group_builds = MockClient::get_group_builds("paper", "1.16").await.unwrap();
+    assert_eq!(group_builds.builds.iter().map(|b| b.build).collect::<Vec<_>>(), vec![416, 793, 794]);
+
+    let version = MockClient::get_version_info("paper", "1.16.5").await.unwrap();
+    assert_eq!(version.builds, vec![793, 794]);
+
+    let build = MockClient::get_version_builds("paper", "1.16.5", 794).await.unwrap();
+    assert_eq!(build.downloads.application.name, "paper-1.16.5-794.jar");
+
+    assert!(MockClient::get_project("waterfall").await.is_err());
+
+    let dir = temp_dir("mock_download");
+    let target = dir.join("paper.jar");
+    BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar")
+        .to_path::<MockClient, _>(&target, true, Some(&build.downloads.application.sha256))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&target).unwrap(), b"paper 794");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn mock_server_injects_faults() {
+    let options = MockOptions { truncate_downloads: true, fail_every: Some(4), ..MockOptions::default() };
+    let _server = start_mock(options).await;
+
+    let build = MockClient::get_version_builds("paper", "1.16.5", 794).await.unwrap();
+    let dir = temp_dir("mock_truncated");
+    let target = dir.join("paper.jar");
+    let result = BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar")
+        .to_path::<MockClient, _>(&target, true, Some(&build.downloads.application.sha256))
+        .await;
+    assert!(!result.unwrap_err().to_string().contains("Hash mismatch"));
+    assert!(!target.exists());
+    // The transfer itself fails, even without a hash to check against.
+    let result = BuildDownloadRequest::new("paper", "1.16.5", 793, "paper-1.16.5-793.jar")
+        .to_path::<MockClient, _>(&target, true, None)
+        .await;
+    assert!(result.is_err());
+    assert!(!target.exists());
+
+    assert!(MockClient::get_projects().await.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mock_fixtures_are_read_from_json_and_toml() {
+    let dir = temp_dir("mock_fixture");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("fixture.toml"), r#"
+[[projects]]
+project_id = "paper"
+project_name = "Paper"
+version_groups = [{ name = "1.16", versions = ["1.16.5"] }]
+
+[[projects.builds]]
+version = "1.16.5"
+build = 794
+time = "2021-07-01T12:00:00.000Z"
+changes = [{ commit = "ccccccc", summary = "Add new API", message = "Add new API" }]
+download = { name = "paper-1.16.5-794.jar", file = "paper.jar" }
+"#).unwrap();
+    std::fs::write(dir.join("fixture.json"), serde_json::to_vec(&mock_fixture()).unwrap()).unwrap();
+
+    let fixture = MockFixture::from_path(dir.join("fixture.toml")).unwrap();
+    let build = &fixture.projects[0].builds[0];
+    assert_eq!((build.build, build.changes[0].summary.as_str()), (794, "Add new API"));
+    assert_eq!(build.download.file, Some(dir.join("paper.jar")));
+    assert_eq!(MockFixture::from_path(dir.join("fixture.json")).unwrap().projects[0].builds.len(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ranges_are_parsed() {
+    use super::server::parse_range;
+
+    assert_eq!(parse_range("bytes=0-4", 10), Some((0, 4)));
+    assert_eq!(parse_range("bytes=5-", 10), Some((5, 9)));
+    assert_eq!(parse_range("bytes=-3", 10), Some((7, 9)));
+    assert_eq!(parse_range("bytes=8-20", 10), Some((8, 9)));
+    assert_eq!(parse_range("bytes=10-", 10), None);
+    assert_eq!(parse_range("bytes=0-1,3-4", 10), None);
+    assert_eq!(parse_range("items=0-1", 10), None);
+}
+
+#[tokio::test]
+async fn mirror_server_caches_upstream() {
+    use super::mirror::{MirrorOptions, MirrorServer, MirrorStore};
+
+    let upstream = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("mirror");
+    let store = MirrorStore::new(&dir);
+    let options = MirrorOptions { ttl: std::time::Duration::from_secs(0) };
+    let mirror = MirrorServer::start::<MockClient>(store.clone(), options, ([127, 0, 0, 1], 0).into()).await.unwrap();
+    MIRROR_URL.with(|url| *url.borrow_mut() = mirror.url());
+
+    assert_eq!(MirrorClient::get_version_info("paper", "1.16.5").await.unwrap().builds, vec![793, 794]);
+    let build = MirrorClient::get_version_builds("paper", "1.16.5", 794).await.unwrap();
+    let mut jar = Vec::new();
+    BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar")
+        .copy_to::<MirrorClient, _>(&mut jar)
+        .await
+        .unwrap();
+    assert_eq!(jar, b"paper 794");
+    assert!(store.object_path(&build.downloads.application.sha256).unwrap().is_file());
+    assert!(MirrorClient::get_project("waterfall").await.is_err());
+
+    drop(upstream);
+    tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+
+    assert_eq!(MirrorClient::get_version_info("paper", "1.16.5").await.unwrap().builds, vec![793, 794]);
+    let request = hyper::Request::get(format!("{}/v2/projects/paper/versions/1.16.5/builds/794/downloads/paper-1.16.5-794.jar", mirror.url()))
+        .header(hyper::header::RANGE, "bytes=6-")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(&hyper::body::to_bytes(response.into_body()).await.unwrap()[..], b"794");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn version_specs_match() {
+    use super::version::VersionSpec;
+
+    let versions: Vec<String> = vec!["1.15.2", "1.16", "1.16.4", "1.16.5", "1.17"].into_iter().map(String::from).collect();
+    let spec: VersionSpec = "1.16.x".parse().unwrap();
+    assert_eq!(spec.select(&versions), vec!["1.16", "1.16.4", "1.16.5"]);
+    assert_eq!(spec.latest(&versions).unwrap(), "1.16.5");
+    assert_eq!("latest".parse::<VersionSpec>().unwrap().select(&versions), vec!["1.17"]);
+    assert_eq!("*".parse::<VersionSpec>().unwrap().select(&versions).len(), 5);
+    assert_eq!("1.16.4".parse::<VersionSpec>().unwrap().select(&versions), vec!["1.16.4"]);
+    assert!("1.x.5".parse::<VersionSpec>().is_err());
+    assert!("../1.16".parse::<VersionSpec>().is_err());
+}
+
+#[tokio::test]
+async fn mirror_sync_is_incremental() {
+    use super::mirror::{mirror_sync, static_path};
+    use super::server::Route;
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("mirror_sync");
+    let spec = "1.16.x".parse().unwrap();
+
+    let report = mirror_sync::<MockClient>("paper", &spec, &dir).await.unwrap();
+    assert_eq!((report.builds_fetched, report.downloaded), (3, 3));
+    assert!(dir.join("v2/projects/paper/version_group/1.16/builds/index.json").is_file());
+    assert!(!dir.join("v2/projects/paper/version_group/1.17").exists());
+
+    let jar = static_path(&dir, &Route::Download("paper".into(), "1.16.5".into(), 794, "paper-1.16.5-794.jar".into()));
+    std::fs::write(&jar, b"corrupted").unwrap();
+
+    let report = mirror_sync::<MockClient>("paper", &spec, &dir).await.unwrap();
+    assert_eq!((report.builds_fetched, report.builds_skipped), (0, 3));
+    assert_eq!((report.downloaded, report.verified, report.repaired), (0, 2, 1));
+    assert_eq!(std::fs::read(&jar).unwrap(), b"paper 794");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Hosts a static mirror tree like nginx's `try_files $uri $uri/index.json =404`.
+async fn serve_static(root: std::path::PathBuf) -> std::net::SocketAddr {
+    use hyper::service::{make_service_fn, service_fn};
+
+    let make_service = make_service_fn(move |_| {
+        let root = root.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |request: hyper::Request<hyper::Body>| {
+                let path = root.join(request.uri().path().trim_start_matches('/'));
+                async move {
+                    let file = if path.is_file() { path } else { path.join("index.json") };
+                    Ok::<_, std::convert::Infallible>(match std::fs::read(&file) {
+                        Ok(bytes) => hyper::Response::new(hyper::Body::from(bytes)),
+                        Err(_) => hyper::Response::builder().status(hyper::StatusCode::NOT_FOUND).body(hyper::Body::empty()).unwrap(),
+                    })
+                }
+            }))
+        }
+    });
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test]
+async fn mirror_sync_tree_serves_the_client() {
+    use super::mirror::mirror_sync;
+
+    let upstream = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("mirror_sync_static");
+    mirror_sync::<MockClient>("paper", &"1.16.x".parse().unwrap(), &dir).await.unwrap();
+    drop(upstream);
+
+    let addr = serve_static(dir.clone()).await;
+    MIRROR_URL.with(|url| *url.borrow_mut() = format!("http://{}", addr));
+
+    assert_eq!(MirrorClient::get_project("paper").await.unwrap().versions, vec!["1.16.4", "1.16.5", "1.17"]);
+    assert_eq!(MirrorClient::get_group_builds("paper", "1.16").await.unwrap().builds.len(), 3);
+    assert_eq!(MirrorClient::get_version_info("paper", "1.16.5").await.unwrap().builds, vec![793, 794]);
+    let build = MirrorClient::get_version_builds("paper", "1.16.5", 794).await.unwrap();
+    let jar = dir.join("download").join("paper.jar");
+    BuildDownloadRequest::new("paper", "1.16.5", 794, "paper-1.16.5-794.jar")
+        .to_path::<MirrorClient, _>(&jar, true, Some(&build.downloads.application.sha256))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&jar).unwrap(), b"paper 794");
+    assert!(MirrorClient::get_version_info("paper", "1.17").await.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn new_builds_follow_watch_state() {
+    use super::watch::{WatchState, new_builds};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("watch");
+    let state_path = dir.join("state.json");
+
+    let mut state = WatchState::load(&state_path).unwrap();
+    assert_eq!(state.last_build("paper", "1.16.5"), None);
+
+    let events = new_builds::<MockClient>("paper", "1.16.5", Some(793)).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].build, 794);
+    assert!(events[0].download_url.ends_with("/v2/projects/paper/versions/1.16.5/builds/794/downloads/paper-1.16.5-794.jar"));
+    assert!(events[0].env().contains(&("PAPER_BUILD", String::from("794"))));
+
+    state.set_last_build("paper", "1.16.5", 794);
+    state.set_last_build("paper", "1.16.5", 793);
+    state.save(&state_path).unwrap();
+    assert_eq!(WatchState::load(&state_path).unwrap().last_build("paper", "1.16.5"), Some(794));
+    assert!(new_builds::<MockClient>("paper", "1.16.5", Some(794)).await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn build_watch_streams_new_builds() {
+    use futures_util::StreamExt;
+    use super::watch::{BuildWatch, WatchState};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let interval = std::time::Duration::from_millis(10);
+
+    let mut cursor = WatchState::default();
+    cursor.set_last_build("paper", "1.16.5", 793);
+    let mut watch = BuildWatch::new("paper", "1.16.x".parse().unwrap(), interval).cursor(cursor);
+    let events = watch.poll::<MockClient>().await.unwrap();
+    assert_eq!(events.iter().map(|e| e.build).collect::<Vec<_>>(), vec![794]);
+    assert_eq!(events[0].sha256, super::download::sha256_bytes(b"paper 794"));
+    assert_eq!(watch.current_cursor().last_build("paper", "1.16.4"), Some(416));
+
+    let stream = BuildWatch::new("paper", "1.16.x".parse().unwrap(), interval)
+        .emit_existing(true)
+        .stream::<MockClient>();
+    let events: Vec<_> = Box::pin(stream).take(3).collect().await;
+    let builds: Vec<i32> = events.into_iter().map(|event| event.unwrap().build).collect();
+    assert_eq!(builds, vec![416, 793, 794]);
+}
+
+#[tokio::test]
+async fn feeds_render_group_builds() {
+    use super::feed::{FeedFormat, build_feed};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let sources = vec![(String::from("paper"), String::from("1.16"))];
+
+    let atom = build_feed::<MockClient>(&sources, FeedFormat::Atom, 2).await.unwrap();
+    assert_eq!(atom.matches("<entry>").count(), 2);
+    assert!(atom.find("Paper 1.16.5 build #794").unwrap() < atom.find("Paper 1.16.5 build #793").unwrap());
+    assert!(!atom.contains("build #416"));
+    assert!(atom.contains("<updated>2021-07-01T12:00:00+00:00</updated>"));
+    assert!(atom.contains("&lt;code&gt;ccccccc&lt;/code&gt; Add new API"));
+
+    let rss = build_feed::<MockClient>(&sources, FeedFormat::Rss, 50).await.unwrap();
+    assert_eq!(rss.matches("<item>").count(), 3);
+    assert!(rss.contains("<pubDate>Thu, 1 Jul 2021 12:00:00 +0000</pubDate>"));
+}
+
+#[tokio::test]
+async fn release_notes_link_commits_and_issues() {
+    use super::notes::{NotesFormat, NotesOptions, render_notes};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let builds = MockClient::get_builds_of_version("paper", "1.16.5").await.unwrap();
+    assert_eq!(builds.len(), 2);
+
+    let options = NotesOptions { repository: Some(String::from("https://github.com/PaperMC/Paper")), ..NotesOptions::default() };
+    let markdown = render_notes(&builds, &options);
+    assert!(markdown.find("### Build #794").unwrap() < markdown.find("### Build #793").unwrap());
+    assert!(markdown.contains("- [`bbbbbbb`](https://github.com/PaperMC/Paper/commit/bbbbbbb) Fix chunk loading ([#5926](https://github.com/PaperMC/Paper/issues/5926))"));
+
+    let options = NotesOptions { format: NotesFormat::Html, ..NotesOptions::default() };
+    let html = render_notes(&builds, &options);
+    assert!(html.contains("<h2>1.16.5</h2>"));
+    assert!(html.contains("<li><code>bbbbbbb</code> Fix chunk loading (#5926)</li>"));
+}
+
+#[tokio::test]
+async fn changes_are_searched_oldest_first() {
+    use super::paper::parse_instant;
+    use super::search::{SearchPattern, SearchQuery, search_group_changes};
+
+    let _server = start_mock(MockOptions::default()).await;
+
+    let query = SearchQuery::new(SearchPattern::keywords("CHUNK loading"));
+    let matches = search_group_changes::<MockClient>("paper", "1.16", &query).await.unwrap();
+    assert_eq!(matches.iter().map(|m| m.build).collect::<Vec<_>>(), vec![793]);
+
+    let mut query = SearchQuery::new(SearchPattern::regex(r"^(fix|add)\b").unwrap());
+    assert_eq!(search_group_changes::<MockClient>("paper", "1.16", &query).await.unwrap().len(), 2);
+    query.since = Some(parse_instant("2021-06-25").unwrap());
+    let matches = search_group_changes::<MockClient>("paper", "1.16", &query).await.unwrap();
+    assert_eq!(matches.iter().map(|m| m.build).collect::<Vec<_>>(), vec![794]);
+
+    assert!(parse_instant("yesterday").is_err());
+}
+
+#[test]
+fn changes_are_classified() {
+    use super::classify::{ChangeKind, classify};
+    use super::paper::ChangesInfo;
+
+    let change = |summary: &str, message: &str| ChangesInfo {
+        commit: String::from("abcdef0"),
+        summary: summary.to_string(),
+        message: message.to_string(),
+    };
+
+    let upstream = classify(&change(
+        "Updated Upstream (Bukkit/CraftBukkit/Spigot)",
+        "Updated Upstream (Bukkit/CraftBukkit/Spigot)\n\nUpstream has released updates that appear to apply and compile correctly.\n\n\
+         Bukkit Changes:\n8160e29a SPIGOT-6539: Add JavaDoc\n\nCraftBukkit Changes:\n2d5e9e9c SPIGOT-6543: Fix hopper\n4a1f0b6e #861: Add API\n\n\
+         Spigot Changes:\ne0d3d4c6 Rebuild patches\n",
+    ));
+    assert_eq!(upstream.kind, ChangeKind::UpstreamUpdate);
+    assert_eq!(upstream.upstream_projects, vec!["Bukkit", "CraftBukkit", "Spigot"]);
+    assert_eq!(upstream.upstream_commits.iter().map(|c| c.hash.as_str()).collect::<Vec<_>>(),
+               vec!["8160e29a", "2d5e9e9c", "4a1f0b6e", "e0d3d4c6"]);
+    assert_eq!(upstream.upstream_commits[3].project, "Spigot");
+    assert_eq!(upstream.references, Vec::<u32>::new());
+
+    let fix = classify(&change("Fix chunk loading (#5926)", "Fix chunk loading (#5926)\n\nFixes #5900"));
+    assert_eq!(fix.kind, ChangeKind::Fix);
+    assert_eq!(fix.references, vec![5900, 5926]);
+    assert!(fix.upstream_commits.is_empty());
+
+    assert_eq!(classify(&change("Revert \"Add new API\"", "")).kind, ChangeKind::Revert);
+    assert_eq!(classify(&change("Add new API", "")).kind, ChangeKind::Feature);
+    assert_eq!(classify(&change("Optimize entity tracking", "")).kind, ChangeKind::Performance);
+    assert_eq!(classify(&change("Fix book crash exploit", "")).kind, ChangeKind::Security);
+    assert_eq!(classify(&change("Rebuild patches", "")).kind, ChangeKind::Other);
+    assert_eq!(classify(&change("Fix flag parsing", "")).kind, ChangeKind::Fix);
+    assert_eq!(classify(&change("Log debug output", "")).kind, ChangeKind::Other);
+    assert_eq!(classify(&change("Update address padding", "")).kind, ChangeKind::Other);
+    assert_eq!(classify(&change("Rename capital rapid mode", "")).kind, ChangeKind::Other);
+    assert_eq!(classify(&change("Fixes lagging chunks", "")).kind, ChangeKind::Performance);
+    assert_eq!(classify(&change("Added APIs for signs", "")).kind, ChangeKind::Feature);
+}
+
+#[tokio::test]
+async fn builds_are_resolved_at_instants() {
+    use super::paper::parse_instant;
+    use super::resolve::resolve_at;
+    use super::version::VersionSpec;
+
+    let _server = start_mock(MockOptions::default()).await;
+    let resolve = |spec: &str, at: &str| {
+        let spec = spec.parse::<VersionSpec>().unwrap();
+        let at = parse_instant(at).unwrap();
+        async move { resolve_at::<MockClient>("paper", &spec, at).await.unwrap().map(|info| (info.version, info.build)) }
+    };
+
+    assert_eq!(resolve("latest", "2021-06-01").await, Some((String::from("1.16.4"), 416)));
+    assert_eq!(resolve("latest", "2021-07-01").await, Some((String::from("1.16.5"), 793)));
+    assert_eq!(resolve("latest", "2021-07-01T12:00:00Z").await, Some((String::from("1.16.5"), 794)));
+    assert_eq!(resolve("latest", "2021-08-01").await, Some((String::from("1.17"), 1)));
+    assert_eq!(resolve("1.16.x", "2021-08-01").await, Some((String::from("1.16.5"), 794)));
+    assert_eq!(resolve("1.16.5", "2021-06-01").await, None);
+    assert_eq!(resolve("latest", "2020-01-01").await, None);
+}
+
+#[cfg(feature = "index")]
+#[tokio::test]
+async fn index_syncs_incrementally() {
+    use super::download::sha256_bytes;
+    use super::index::BuildIndex;
+
+    let _server = start_mock(MockOptions::default()).await;
+    let mut index = BuildIndex::open_in_memory().unwrap();
+
+    let report = index.sync::<MockClient>(None).await.unwrap();
+    assert_eq!((report.projects, report.versions_checked, report.versions_updated, report.builds_added), (1, 3, 3, 4));
+    let report = index.sync::<MockClient>(None).await.unwrap();
+    assert_eq!((report.versions_checked, report.versions_updated, report.builds_added), (3, 0, 0));
+
+    let found = index.find_by_sha256(&sha256_bytes(b"paper 794")).unwrap().unwrap();
+    assert_eq!((found.version.as_str(), found.build), ("1.16.5", 794));
+    assert!(index.find_by_sha256(&"0".repeat(64)).unwrap().is_none());
+
+    let result = index.query("SELECT version, COUNT(*) AS builds FROM builds GROUP BY version ORDER BY version").unwrap();
+    assert_eq!(result.columns, vec!["version", "builds"]);
+    assert_eq!(result.rows[1], vec![serde_json::json!("1.16.5"), serde_json::json!(2)]);
+    assert!(index.query("SELECT * FROM missing").is_err());
+
+    // Build 793 is withdrawn upstream.
+    let mut fixture = mock_fixture();
+    fixture.projects[0].builds.retain(|build| build.build != 793);
+    let server = MockServer::start(fixture, MockOptions::default(), ([127, 0, 0, 1], 0).into()).await.unwrap();
+    MOCK_URL.with(|url| *url.borrow_mut() = server.url());
+    let report = index.sync::<MockClient>(None).await.unwrap();
+    assert_eq!((report.versions_updated, report.builds_added, report.builds_removed), (1, 0, 1));
+    assert!(index.find_by_sha256(&sha256_bytes(b"paper 793")).unwrap().is_none());
+    for table in &["builds", "changes", "downloads"] {
+        let result = index.query(&format!("SELECT COUNT(*) FROM {} WHERE build = 793", table)).unwrap();
+        assert_eq!(result.rows[0], vec![serde_json::json!(0)]);
+    }
+}
+
+#[cfg(feature = "index")]
+#[tokio::test]
+async fn index_backs_hash_lookups() {
+    use super::identify::identify_jar_indexed;
+    use super::index::BuildIndex;
+    use super::provenance::{VerificationSource, verify_jar_indexed};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("index_lookups");
+    std::fs::create_dir_all(&dir).unwrap();
+    let database = dir.join("index.sqlite");
+    BuildIndex::open(&database).unwrap().sync::<MockClient>(None).await.unwrap();
+
+    let index = BuildIndex::open_read_only(&database).unwrap();
+    assert_eq!(index.query("SELECT COUNT(*) FROM builds").unwrap().rows[0], vec![serde_json::json!(4)]);
+    assert!(index.query("DELETE FROM builds").is_err());
+
+    let jar = dir.join("server.jar");
+    std::fs::write(&jar, b"paper 1.17 1").unwrap();
+    let identified = identify_jar_indexed::<MockClient, _>(&index, &jar).await.unwrap().unwrap();
+    assert_eq!((identified.project.as_str(), identified.build.version.as_str(), identified.build.build), ("paper", "1.17", 1));
+    let verification = verify_jar_indexed::<MockClient, _>(&index, &jar).await.unwrap();
+    assert!(verification.verified);
+    assert_eq!(verification.source, Some(VerificationSource::HashLookup));
+    assert_eq!(verification.build, Some(1));
+
+    std::fs::write(&jar, b"unknown").unwrap();
+    assert!(identify_jar_indexed::<MockClient, _>(&index, &jar).await.unwrap().is_none());
+    assert!(!verify_jar_indexed::<MockClient, _>(&index, &jar).await.unwrap().verified);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn build_stats_are_computed() {
+    use super::paper::parse_instant;
+    use super::stats::compute_stats;
+
+    let _server = start_mock(MockOptions::default()).await;
+    let mut builds = MockClient::get_group_builds("paper", "1.16").await.unwrap().builds;
+    builds.extend(MockClient::get_group_builds("paper", "1.17").await.unwrap().builds);
+
+    let report = compute_stats("paper", &builds, parse_instant("2021-07-03T00:00:00Z").unwrap(), 2).unwrap();
+    let versions: Vec<&str> = report.versions.iter().map(|stats| stats.version.as_str()).collect();
+    assert_eq!(versions, vec!["1.16.4", "1.16.5", "1.17"]);
+
+    let stats = &report.versions[1];
+    assert_eq!((stats.builds, stats.changes, stats.last_build), (2, 2, 794));
+    assert_eq!(stats.changes_per_build, 1.0);
+    assert_eq!(stats.median_gap_hours, Some(11.0 * 24.0));
+    assert!((stats.builds_per_week - 2.0 / (11.0 / 7.0)).abs() < 1e-9);
+    assert_eq!(report.versions[2].builds_per_week, 1.0);
+    assert_eq!(stats.last_build_age_hours, 36.0);
+    assert_eq!(report.versions[0].median_gap_hours, None);
+
+    let weeks: Vec<(&str, usize, usize)> = report.most_changed_periods.iter()
+        .map(|period| (period.week_start.as_str(), period.builds, period.changes))
+        .collect();
+    assert_eq!(weeks, vec![("2021-06-28", 2, 1), ("2020-12-21", 1, 1)]);
+}
+
+#[tokio::test]
+async fn unsupported_versions_are_flagged() {
+    use chrono::Duration;
+    use super::identify::identify_jar;
+    use super::paper::parse_instant;
+    use super::support::{SupportPolicy, UnsupportedReason, assess_version, project_support};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let project = MockClient::get_project("paper").await.unwrap();
+    let builds = MockClient::get_group_builds("paper", "1.16").await.unwrap().builds;
+    let now = parse_instant("2021-07-10").unwrap();
+    assert!(!SupportPolicy::default().superseded);
+    let policy = SupportPolicy { max_build_age: Duration::days(30), superseded: true };
+
+    let old = assess_version(&project, "1.16.4", builds.iter().find(|build| build.build == 416), now, &policy).unwrap();
+    assert!(!old.is_supported());
+    assert!(matches!(old.reasons[0], UnsupportedReason::Inactive { age_days: 198, .. }));
+    assert_eq!(old.reasons[1], UnsupportedReason::Superseded { newest: String::from("1.17") });
+    assert!(old.warning().unwrap().contains("1.17 is available"));
+
+    let recent = assess_version(&project, "1.16.5", builds.iter().find(|build| build.build == 794), now, &policy).unwrap();
+    assert_eq!(recent.reasons, vec![UnsupportedReason::Superseded { newest: String::from("1.17") }]);
+    let policy = SupportPolicy { superseded: false, ..policy };
+    let recent = assess_version(&project, "1.16.5", builds.iter().find(|build| build.build == 794), now, &policy).unwrap();
+    assert!(recent.is_supported());
+    assert_eq!(recent.marker(), "supported");
+
+    let policy = SupportPolicy { max_build_age: Duration::days(365 * 100), superseded: true };
+    let support = project_support::<MockClient>("paper", &policy).await.unwrap();
+    let supported: Vec<&str> = support.iter().filter(|s| s.is_supported()).map(|s| s.version.as_str()).collect();
+    assert_eq!(supported, vec!["1.17"]);
+
+    let dir = temp_dir("identify");
+    std::fs::create_dir_all(&dir).unwrap();
+    let jar = dir.join("server.jar");
+    std::fs::write(&jar, b"paper 793").unwrap();
+    let identified = identify_jar::<MockClient, _>("paper", &jar).await.unwrap().unwrap();
+    assert_eq!((identified.build.version.as_str(), identified.build.build), ("1.16.5", 793));
+    std::fs::write(&jar, b"unknown").unwrap();
+    assert!(identify_jar::<MockClient, _>("paper", &jar).await.unwrap().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn versions_are_compared_numerically() {
+    use std::cmp::Ordering;
+    use super::version::compare_versions;
+
+    assert_eq!(compare_versions("1.9", "1.16.5"), Ordering::Less);
+    assert_eq!(compare_versions("1.16", "1.16.5"), Ordering::Less);
+    assert_eq!(compare_versions("1.17", "1.16.5"), Ordering::Greater);
+    assert_eq!(compare_versions("1.16.5", "1.16.5"), Ordering::Equal);
+    assert_eq!(compare_versions("1.17-pre1", "1.17"), Ordering::Less);
+    assert_eq!(compare_versions("1.17-pre2", "1.17-pre10"), Ordering::Less);
+    assert_eq!(compare_versions("1.17-pre5", "1.17-rc1"), Ordering::Less);
+    assert_eq!(compare_versions("1.17-rc1", "1.16.5"), Ordering::Greater);
+    assert_eq!(compare_versions("1.17.1-pre1", "1.17"), Ordering::Greater);
+}
+
+#[tokio::test]
+async fn compatibility_matrix_lists_versions() {
+    use super::matrix::compatibility_matrix;
+
+    let _server = start_mock(MockOptions::default()).await;
+    let matrix = compatibility_matrix::<MockClient>(&[String::from("paper")]).await.unwrap();
+    let versions: Vec<&str> = matrix.rows.iter().map(|row| row.version.as_str()).collect();
+    assert_eq!(versions, vec!["1.17", "1.16.5", "1.16.4"]);
+    assert_eq!(matrix.rows[1].group.as_deref(), Some("1.16"));
+    assert_eq!(matrix.rows[1].cells[0].as_ref().map(|cell| cell.build), Some(794));
+    assert_eq!(matrix.rows[1].group_supported, vec![true]);
+
+    assert!(compatibility_matrix::<MockClient>(&[String::from("paper"), String::from("velocity")]).await.is_err());
+}
+
+#[tokio::test]
+async fn fleet_report_identifies_servers() {
+    use chrono::Duration;
+    use super::fleet::{IdentifiedBy, expand_pattern, fleet_report};
+    use super::identify::parse_version_string;
+    use super::support::SupportPolicy;
+
+    assert_eq!(parse_version_string("git-Paper-794 (MC: 1.16.5)"), Some((String::from("paper"), String::from("1.16.5"), 794)));
+    assert_eq!(parse_version_string("1.16.5"), None);
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("fleet");
+    for name in &["hashed", "history", "unknown"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("hashed").join("paper.jar"), b"paper 793").unwrap();
+    std::fs::write(dir.join("history").join("server.jar"), b"patched").unwrap();
+    std::fs::write(dir.join("history").join("version_history.json"), br#"{"currentVersion":"git-Paper-416 (MC: 1.16.4)"}"#).unwrap();
+
+    let directories = expand_pattern(&format!("{}/*", dir.display())).unwrap();
+    assert_eq!(directories.len(), 3);
+    assert_eq!(directories[0], dir.join("hashed"));
+
+    let policy = SupportPolicy { max_build_age: Duration::days(365 * 100), superseded: true };
+    let report = fleet_report::<MockClient>(&directories, &[String::from("paper")], &policy).await.unwrap();
+
+    let hashed = report[0].installed.as_ref().unwrap();
+    assert_eq!((hashed.version.as_str(), hashed.build, hashed.identified_by), ("1.16.5", 793, IdentifiedBy::Hash));
+    assert_eq!((report[0].latest_build, report[0].builds_behind), (Some(794), 1));
+    assert_eq!(report[0].support.as_ref().unwrap().marker(), "unsupported");
+
+    let history = report[1].installed.as_ref().unwrap();
+    assert_eq!((history.version.as_str(), history.build, history.identified_by), ("1.16.4", 416, IdentifiedBy::VersionHistory));
+    assert_eq!(report[1].builds_behind, 0);
+    assert!(report[1].age_days.unwrap() > 0);
+
+    assert!(report[2].installed.is_none());
+    assert!(report[2].error.is_some());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn fleet_rollout_updates_in_batches_and_rolls_back() {
+    use super::paper::parse_instant;
+    use super::provenance::Provenance;
+    use super::download::sha256_bytes;
+    use super::rollout::{BACKUP_DIR, FleetConfig, MaintenanceWindow, PlannedAction, apply_fleet, backup_jar, plan_fleet, release_server, rollback_jar, rollback_server};
+    use super::store::Lockfile;
+
+    let window = MaintenanceWindow { days: vec![String::from("sat")], start: String::from("22:00"), end: String::from("04:00") };
+    assert!(window.contains(parse_instant("2021-07-03T23:00:00Z").unwrap()).unwrap());
+    assert!(window.contains(parse_instant("2021-07-04T03:00:00Z").unwrap()).unwrap());
+    assert!(!window.contains(parse_instant("2021-07-04T23:00:00Z").unwrap()).unwrap());
+    assert!(!window.contains(parse_instant("2021-07-03T12:00:00Z").unwrap()).unwrap());
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("rollout");
+    for name in &["old", "current", "fresh", "edge"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("old").join("server.jar"), b"paper 793").unwrap();
+    std::fs::write(dir.join("current").join("server.jar"), b"paper 794").unwrap();
+    let locked = |build: i32, content: &[u8]| Lockfile {
+        project: String::from("paper"),
+        version: String::from("1.16.5"),
+        build,
+        sha256: sha256_bytes(content),
+        jar: String::from("server.jar"),
+    };
+    locked(793, b"paper 793").write(dir.join("old")).unwrap();
+
+    let config: FleetConfig = serde_json::from_value(serde_json::json!({
+        "batch_size": 1,
+        "write_provenance": true,
+        "policy": { "versions": "1.16.x", "min_build_age_hours": 24 },
+        "servers": [
+            { "name": "old", "directory": dir.join("old"), "jar": "server.jar" },
+            { "name": "current", "directory": dir.join("current") },
+            { "name": "fresh", "directory": dir.join("fresh") },
+            { "name": "edge", "directory": dir.join("edge"), "policy": { "channel": "experimental" } },
+            { "name": "stable", "directory": dir.join("edge"), "policy": { "versions": "1.17" } }
+        ]
+    })).unwrap();
+
+    let plans = plan_fleet::<MockClient>(&config, parse_instant("2021-07-01T18:00:00Z").unwrap()).await.unwrap();
+    assert_eq!(plans[0].action, PlannedAction::UpToDate);
+    assert_eq!(plans[1].action, PlannedAction::UpToDate);
+
+    let plans = plan_fleet::<MockClient>(&config, parse_instant("2021-07-10").unwrap()).await.unwrap();
+    assert!(matches!(&plans[0].action, PlannedAction::Update { build: 794, .. }));
+    assert_eq!(plans[1].action, PlannedAction::UpToDate);
+    assert!(matches!(&plans[2].action, PlannedAction::Update { build: 794, .. }));
+    assert!(matches!(&plans[3].action, PlannedAction::Update { version, build: 1, .. } if version == "1.17"));
+    assert!(matches!(&plans[4].action, PlannedAction::Skipped { .. }));
+
+    let outcomes = apply_fleet::<MockClient>(&config, &plans).await.unwrap();
+    assert!(outcomes.iter().all(|outcome| outcome.error.is_none()));
+    assert_eq!(std::fs::read(dir.join("old").join("server.jar")).unwrap(), b"paper 794");
+    assert_eq!(std::fs::read(dir.join("fresh").join("server.jar")).unwrap(), b"paper 794");
+    assert_eq!(std::fs::read(dir.join("edge").join("server.jar")).unwrap(), b"paper 1.17 1");
+    let old = outcomes.iter().find(|outcome| outcome.server == "old").unwrap();
+    assert_eq!(old.backup.as_ref().unwrap().installed.as_ref().unwrap().build, 793);
+
+    assert_eq!(Provenance::read(dir.join("old").join("server.jar")).unwrap().unwrap().build, 794);
+    assert_eq!(Lockfile::read(dir.join("old")).unwrap().unwrap().build, 794);
+
+    let backup = rollback_server::<MockClient>(&config, "old").await.unwrap();
+    assert_eq!(backup.jar, "server.jar");
+    assert_eq!(std::fs::read(dir.join("old").join("server.jar")).unwrap(), b"paper 793");
+    assert!(Provenance::read(dir.join("old").join("server.jar")).unwrap().is_none());
+    assert_eq!(Lockfile::read(dir.join("old")).unwrap().unwrap(), locked(793, b"paper 793"));
+    assert!(rollback_server::<MockClient>(&config, "old").await.is_err());
+    assert!(rollback_server::<MockClient>(&config, "missing").await.is_err());
+
+    // The rollback holds the server at the restored jar until it is released.
+    let plans = plan_fleet::<MockClient>(&config, parse_instant("2021-07-10").unwrap()).await.unwrap();
+    assert!(matches!(&plans[0].action, PlannedAction::Skipped { reason } if reason.starts_with("Held since the rollback")));
+    apply_fleet::<MockClient>(&config, &plans).await.unwrap();
+    assert_eq!(std::fs::read(dir.join("old").join("server.jar")).unwrap(), b"paper 793");
+    assert!(release_server(&config, "old").unwrap().unwrap().installed.is_some());
+    assert!(release_server(&config, "old").unwrap().is_none());
+    let plans = plan_fleet::<MockClient>(&config, parse_instant("2021-07-10").unwrap()).await.unwrap();
+    assert!(matches!(&plans[0].action, PlannedAction::Update { build: 794, .. }));
+
+    // Backups made before lockfiles were backed up rewrite the lockfile from the restored build.
+    let jar = dir.join("old").join("server.jar");
+    let without_lockfiles = || {
+        let manifest = dir.join("old").join(BACKUP_DIR).join("backups.json");
+        let mut backups: serde_json::Value = serde_json::from_slice(&std::fs::read(&manifest).unwrap()).unwrap();
+        backups.as_array_mut().unwrap().iter_mut().for_each(|backup| { backup.as_object_mut().unwrap().remove("lockfile"); });
+        std::fs::write(&manifest, serde_json::to_vec(&backups).unwrap()).unwrap();
+    };
+    backup_jar(&jar, backup.installed.as_ref(), 3).unwrap();
+    without_lockfiles();
+    std::fs::write(&jar, b"paper 794").unwrap();
+    locked(794, b"paper 794").write(dir.join("old")).unwrap();
+    rollback_jar(&jar).unwrap();
+    assert_eq!(Lockfile::read(dir.join("old")).unwrap().unwrap(), locked(793, b"paper 793"));
+    backup_jar(&jar, None, 3).unwrap();
+    without_lockfiles();
+    rollback_jar(&jar).unwrap();
+    assert!(Lockfile::read(dir.join("old")).unwrap().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn fleet_apply_skips_servers_once_their_window_closed() {
+    use chrono::{Duration, Utc};
+    use super::rollout::{FleetConfig, PlannedAction, apply_fleet, plan_fleet};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("rollout_window");
+    std::fs::create_dir_all(&dir).unwrap();
+    let now = Utc::now();
+    let window = serde_json::json!({
+        "start": (now - Duration::hours(2)).format("%H:%M").to_string(),
+        "end": (now - Duration::hours(1)).format("%H:%M").to_string(),
+    });
+    let config: FleetConfig = serde_json::from_value(serde_json::json!({
+        "policy": { "versions": "1.16.x", "maintenance_window": window },
+        "servers": [{ "name": "late", "directory": dir }]
+    })).unwrap();
+
+    let plans = plan_fleet::<MockClient>(&config, now - Duration::minutes(90)).await.unwrap();
+    assert!(matches!(&plans[0].action, PlannedAction::Update { build: 794, .. }));
+    let outcomes = apply_fleet::<MockClient>(&config, &plans).await.unwrap();
+    assert_eq!(outcomes[0].action, PlannedAction::Skipped { reason: String::from("Outside of the maintenance window.") });
+    assert!(!dir.join("server.jar").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn failed_fleet_updates_keep_the_backups() {
+    use super::paper::parse_instant;
+    use super::rollout::{BACKUP_DIR, FleetConfig, PlannedAction, apply_fleet, backup_jar, plan_fleet, rollback_server};
+
+    let _server = start_mock(MockOptions { truncate_downloads: true, ..MockOptions::default() }).await;
+    let dir = temp_dir("rollout_failed");
+    for store in &[None, Some(dir.join("store"))] {
+        let server_dir = dir.join("server");
+        std::fs::create_dir_all(&server_dir).unwrap();
+        let jar = server_dir.join("server.jar");
+        std::fs::write(&jar, b"paper 416").unwrap();
+        backup_jar(&jar, None, 2).unwrap();
+        std::fs::write(&jar, b"paper 793").unwrap();
+
+        let config: FleetConfig = serde_json::from_value(serde_json::json!({
+            "keep_backups": 2,
+            "store": store,
+            "policy": { "versions": "1.16.x" },
+            "servers": [{ "name": "server", "directory": server_dir }]
+        })).unwrap();
+        let plans = plan_fleet::<MockClient>(&config, parse_instant("2021-07-10").unwrap()).await.unwrap();
+        assert!(matches!(&plans[0].action, PlannedAction::Update { build: 794, .. }));
+        for _ in 0..2 {
+            let outcomes = apply_fleet::<MockClient>(&config, &plans).await.unwrap();
+            assert!(outcomes[0].error.is_some());
+            assert!(outcomes[0].backup.is_none());
+        }
+        assert_eq!(std::fs::read(&jar).unwrap(), b"paper 793");
+        let backups: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(server_dir.join(BACKUP_DIR).join("backups.json")).unwrap()).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(std::fs::read_dir(&server_dir).unwrap().count(), 2);
+
+        rollback_server::<MockClient>(&config, "server").await.unwrap();
+        assert_eq!(std::fs::read(&jar).unwrap(), b"paper 416");
+        std::fs::remove_dir_all(&server_dir).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn identified_jars_are_assessed_in_their_own_project() {
+    use super::identify::identify_jar;
+    use super::mock::MockProject;
+    use super::provenance::download_build;
+    use super::support::{SupportPolicy, version_support};
+
+    let mut fixture = mock_fixture();
+    fixture.projects.push(serde_json::from_value::<MockProject>(serde_json::json!({
+        "project_id": "velocity",
+        "project_name": "Velocity",
+        "version_groups": [{ "name": "3.0.0", "versions": ["3.0.0"] }],
+        "builds": [{
+            "version": "3.0.0", "build": 70, "time": "2021-07-01T12:00:00.000Z",
+            "download": { "name": "velocity-3.0.0-70.jar", "content": "velocity 70" }
+        }]
+    })).unwrap());
+    let server = MockServer::start(fixture, MockOptions::default(), ([127, 0, 0, 1], 0).into()).await.unwrap();
+    MOCK_URL.with(|url| *url.borrow_mut() = server.url());
+
+    let dir = temp_dir("identify_velocity");
+    let jar = dir.join("proxy.jar");
+    let response = MockClient::get_version_builds("velocity", "3.0.0", 70).await.unwrap();
+    download_build::<MockClient, _>(&response, &jar, true, true).await.unwrap();
+
+    let identified = identify_jar::<MockClient, _>("paper", &jar).await.unwrap().unwrap();
+    assert_eq!((identified.project.as_str(), identified.build.version.as_str()), ("velocity", "3.0.0"));
+    let support = version_support::<MockClient>(&identified.project, &identified.build.version, &SupportPolicy::default()).await.unwrap();
+    assert_eq!((support.project.as_str(), support.last_build), ("velocity", Some(70)));
+    assert!(version_support::<MockClient>("paper", &identified.build.version, &SupportPolicy::default()).await.is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn provenance_sidecars_are_written_and_read_first() {
+    use super::identify::identify_jar;
+    use super::provenance::{Provenance, VerificationSource, download_build, verify_jar};
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("provenance");
+    let jar = dir.join("server.jar");
+    let response = MockClient::get_version_builds("paper", "1.16.5", 793).await.unwrap();
+
+    let provenance = download_build::<MockClient, _>(&response, &jar, true, true).await.unwrap();
+    assert_eq!(Provenance::sidecar_path(&jar), dir.join("server.jar.paper.json"));
+    assert_eq!(Provenance::read(&jar).unwrap().unwrap(), provenance);
+    assert_eq!((provenance.build, provenance.sha256.as_str()), (793, response.downloads.application.sha256.as_str()));
+    assert!(provenance.source_url.ends_with("/v2/projects/paper/versions/1.16.5/builds/793/downloads/paper-1.16.5-793.jar"));
+
+    let identified = identify_jar::<MockClient, _>("velocity", &jar).await.unwrap().unwrap();
+    assert_eq!((identified.project.as_str(), identified.build.build), ("paper", 793));
+    assert!(identified.provenance.is_some());
+
+    let verification = verify_jar::<MockClient, _>("paper", &jar).await.unwrap();
+    assert!(verification.verified);
+    assert_eq!(verification.source, Some(VerificationSource::Provenance));
+
+    std::fs::write(&jar, b"paper 794").unwrap();
+    let verification = verify_jar::<MockClient, _>("paper", &jar).await.unwrap();
+    assert!(!verification.verified);
+    assert!(verification.reason.is_some());
+    let identified = identify_jar::<MockClient, _>("paper", &jar).await.unwrap().unwrap();
+    assert_eq!(identified.build.build, 794);
+    assert!(identified.provenance.is_none());
+
+    download_build::<MockClient, _>(&response, &jar, false, false).await.unwrap();
+    assert!(Provenance::read(&jar).unwrap().is_none());
+    let verification = verify_jar::<MockClient, _>("paper", &jar).await.unwrap();
+    assert_eq!((verification.verified, verification.source), (true, Some(VerificationSource::HashLookup)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn jar_store_links_jars_and_collects_garbage() {
+    use super::provenance::Provenance;
+    use super::store::{JarStore, LinkMode, Lockfile, locked_hashes};
+    use std::collections::HashSet;
+
+    let server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("store");
+    let store = JarStore::new(dir.join("store")).with_link_mode(LinkMode::Hardlink);
+    let older = MockClient::get_version_builds("paper", "1.16.5", 793).await.unwrap();
+    let newer = MockClient::get_version_builds("paper", "1.16.5", 794).await.unwrap();
+
+    let (provenance, method) = store.install::<MockClient, _>(&older, dir.join("a").join("server.jar"), true, true).await.unwrap();
+    assert_eq!((provenance.build, method), (793, LinkMode::Hardlink));
+    assert_eq!(std::fs::read(dir.join("a").join("server.jar")).unwrap(), b"paper 793");
+    assert_eq!(Provenance::read(dir.join("a").join("server.jar")).unwrap().unwrap().sha256, provenance.sha256);
+    assert_eq!(store.fetch::<MockClient>(&newer).await.unwrap().provenance.unwrap().build, 794);
+
+    // Stored jars are linked without downloading them again.
+    drop(server);
+    let store = store.with_link_mode(LinkMode::Copy);
+    let (_, method) = store.install::<MockClient, _>(&older, dir.join("b").join("server.jar"), true, false).await.unwrap();
+    assert_eq!(method, LinkMode::Copy);
+    assert_eq!(std::fs::read(dir.join("b").join("server.jar")).unwrap(), b"paper 793");
+    assert!(Provenance::read(dir.join("b").join("server.jar")).unwrap().is_none());
+    assert_eq!(store.entries().unwrap().len(), 2);
+
+    let report = store.gc(1, &HashSet::new(), true).unwrap();
+    assert_eq!(report.removed.iter().map(|entry| entry.sha256.as_str()).collect::<Vec<_>>(), vec![older.downloads.application.sha256.as_str()]);
+    assert_eq!(report.freed_bytes, 9);
+    assert_eq!(store.entries().unwrap().len(), 2);
+
+    Lockfile::from_provenance(&provenance, "server.jar").write(dir.join("a")).unwrap();
+    let locked = locked_hashes(&[dir.join("a"), dir.join("b")]).unwrap();
+    let report = store.gc(0, &locked, false).unwrap();
+    assert_eq!((report.kept.len(), report.removed.len()), (1, 1));
+    let entries = store.entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].provenance.as_ref().unwrap().build, 793);
+    assert!(!Provenance::sidecar_path(store.object_path(&newer.downloads.application.sha256).unwrap()).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn init_scaffolds_a_server_directory() {
+    use super::paper::BuildChannel;
+    use super::provenance::Provenance;
+    use std::path::Path;
+    use super::scaffold::{InitOptions, init_server, parse_memory, start_script, systemd_unit};
+    use super::store::Lockfile;
+
+    assert_eq!(parse_memory("4G").unwrap(), 4096);
+    assert_eq!(parse_memory("512m").unwrap(), 512);
+    assert!(parse_memory("lots").is_err());
+
+    let _server = start_mock(MockOptions::default()).await;
+    let dir = temp_dir("init").join("lobby");
+    let options = InitOptions {
+        versions: "1.16.x".parse().unwrap(),
+        max_memory: String::from("4G"),
+        jvm_flags: vec![String::from("-Dfile.encoding=UTF-8")],
+        user: Some(String::from("minecraft")),
+        group: Some(String::from("games")),
+        ..InitOptions::default()
+    };
+
+    let options = InitOptions { java: dir.join("missing-java").display().to_string(), ..options };
+    let report = init_server::<MockClient, _>(&dir, &options).await.unwrap();
+    assert_eq!((report.provenance.version.as_str(), report.provenance.build), ("1.16.5", 794));
+    assert_eq!(std::fs::read(dir.join("server.jar")).unwrap(), b"paper 794");
+    assert_eq!(Provenance::read(dir.join("server.jar")).unwrap().unwrap().build, 794);
+    assert_eq!(Lockfile::read(&dir).unwrap().unwrap().sha256, report.provenance.sha256);
+    assert!(!dir.join("eula.txt").exists());
+    let script = std::fs::read_to_string(dir.join("start.sh")).unwrap();
+    assert!(script.contains(&format!("exec {} -Xms2048M -Xmx4096M -XX:+UseG1GC", options.java)));
+    assert!(script.contains("-XX:G1HeapRegionSize=8M"));
+    assert!(script.ends_with("-Dfile.encoding=UTF-8 -jar server.jar nogui\n"));
+    let unit = std::fs::read_to_string(dir.join("lobby.service")).unwrap();
+    assert!(unit.contains("Description=Minecraft server lobby (paper 1.16.5)"));
+    assert!(unit.contains(&format!("WorkingDirectory={}\n", report.directory.display())));
+    assert!(unit.contains("User=minecraft\nGroup=games\n"));
+
+    assert!(init_server::<MockClient, _>(&dir, &options).await.is_err());
+    let options = InitOptions {
+        channel: BuildChannel::Experimental,
+        java: dir.join("missing-java").display().to_string(),
+        accept_eula: true,
+        force: true,
+        ..InitOptions::default()
+    };
+    let report = init_server::<MockClient, _>(&dir, &options).await.unwrap();
+    assert_eq!((report.provenance.version.as_str(), report.provenance.build), ("1.17", 1));
+    assert!(report.warnings[0].ends_with("Java compatibility was not checked."));
+    assert!(std::fs::read_to_string(dir.join("eula.txt")).unwrap().ends_with("eula=true\n"));
+
+    // The newest version only has experimental builds, so latest falls back to 1.16.5.
+    let latest = dir.with_file_name("latest");
+    let options = InitOptions { java: dir.join("missing-java").display().to_string(), ..InitOptions::default() };
+    let report = init_server::<MockClient, _>(&latest, &options).await.unwrap();
+    assert_eq!((report.provenance.version.as_str(), report.provenance.build), ("1.16.5", 794));
+    let missing = dir.with_file_name("missing");
+    assert!(init_server::<MockClient, _>(&missing, &InitOptions { versions: "1.18".parse().unwrap(), ..options }).await.is_err());
+    assert!(!missing.exists());
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+
+    let proxy = start_script(&InitOptions { project: String::from("velocity"), jar: String::from("proxy.jar"), ..InitOptions::default() }).unwrap();
+    assert!(proxy.ends_with("java -Xms2048M -Xmx2048M -jar proxy.jar\n"));
+    assert!(!systemd_unit(Path::new("/srv/proxy"), "Proxy", None, None).contains("User="));
+}
+
+#[test]
+fn java_requirements_and_versions() {
+    use super::java::{JavaRange, JavaStatus, java_requirement, parse_java_version, required_java};
+    use super::paper::ProjectVersionInfoResponse;
+
+    assert_eq!(java_requirement("paper", "1.16.5"), Some(JavaRange { min: 8, max: Some(17) }));
+    assert_eq!(java_requirement("paper", "1.17.1"), Some(JavaRange { min: 16, max: Some(17) }));
+    assert_eq!(java_requirement("paper", "1.18.2").unwrap().to_string(), "Java 17+");
+    assert_eq!(java_requirement("paper", "1.20.5-rc1").unwrap().min, 21);
+    assert_eq!(java_requirement("paper", "1.17-pre1"), Some(JavaRange { min: 16, max: Some(17) }));
+    assert_eq!(java_requirement("paper", "1.7.10"), None);
+    assert_eq!(java_requirement("waterfall", "1.17"), None);
+    let version: ProjectVersionInfoResponse = serde_json::from_value(serde_json::json!({
+        "project_id": "paper", "project_name": "Paper", "version": "1.17", "builds": [1]
+    })).unwrap();
+    assert_eq!(required_java(&version).unwrap().to_string(), "Java 16-17");
+
+    let range = JavaRange { min: 16, max: Some(17) };
+    assert_eq!((range.status(11), range.status(16), range.status(18)), (JavaStatus::TooOld, JavaStatus::Compatible, JavaStatus::TooNew));
+
+    assert_eq!(parse_java_version("java version \"1.8.0_292\"\nJava(TM) SE Runtime Environment"), Some(8));
+    assert_eq!(parse_java_version("openjdk version \"16.0.1\" 2021-04-20"), Some(16));
+    assert_eq!(parse_java_version("openjdk version \"17\" 2021-09-14"), Some(17));
+    assert_eq!(parse_java_version("openjdk version \"21-ea\" 2023-09-19"), Some(21));
+    assert_eq!(parse_java_version("command not found"), None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn java_is_enforced_with_the_detected_runtime() {
+    use super::java::{JavaStatus, check_java, enforce_java};
+    use super::rollout::{FleetConfig, PlannedAction, plan_fleet};
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("java");
+    std::fs::create_dir_all(&dir).unwrap();
+    let java = dir.join("java");
+    std::fs::write(&java, "#!/bin/sh\necho 'openjdk version \"11.0.11\" 2021-04-20' >&2\n").unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let java = java.display().to_string();
+
+    let check = check_java(&java, "paper", "1.17").await.unwrap().unwrap();
+    assert_eq!((check.java, check.status), (11, JavaStatus::TooOld));
+    assert_eq!(enforce_java(&java, "paper", "1.16.5", false).await.unwrap(), None);
+    assert!(enforce_java(&java, "paper", "1.17", false).await.is_err());
+    assert_eq!(enforce_java(&java, "paper", "1.17", true).await.unwrap(), Some(check.message()));
+    assert!(check_java(&dir.join("missing").display().to_string(), "paper", "1.17").await.is_err());
+
+    let _server = start_mock(MockOptions::default()).await;
+    std::fs::create_dir_all(dir.join("edge")).unwrap();
+    let config: FleetConfig = serde_json::from_value(serde_json::json!({
+        "java": java,
+        "policy": { "channel": "experimental" },
+        "servers": [{ "name": "edge", "directory": dir.join("edge") }],
+    })).unwrap();
+    let plans = plan_fleet::<MockClient>(&config, chrono::Utc::now()).await.unwrap();
+    assert_eq!(plans[0].action, PlannedAction::Skipped { reason: check.message() });
+    std::fs::remove_dir_all(&dir).unwrap();
+}
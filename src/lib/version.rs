@@ -0,0 +1,130 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use super::Result;
+
+/// Selects Minecraft versions: `latest`, an exact version such as `1.16.5`, or a prefix with a
+/// trailing wildcard such as `1.16.x`, `1.16.*` or `*`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum VersionSpec {
+    #[default]
+    Latest,
+    Exact(String),
+    Prefix(Vec<String>),
+}
+
+impl VersionSpec {
+    pub fn matches(&self, version: &str) -> bool {
+        match self {
+            VersionSpec::Latest => true,
+            VersionSpec::Exact(exact) => exact == version,
+            VersionSpec::Prefix(prefix) => {
+                let components: Vec<&str> = version.split('.').collect();
+                components.len() >= prefix.len() && prefix.iter().zip(components.iter()).all(|(a, b)| a == b)
+            }
+        }
+    }
+
+    /// The matching versions, keeping the order of `versions`. `Latest` only selects the last one.
+    pub fn select<'a>(&self, versions: &'a [String]) -> Vec<&'a String> {
+        match self {
+            VersionSpec::Latest => versions.last().into_iter().collect(),
+            _ => versions.iter().filter(|version| self.matches(version)).collect(),
+        }
+    }
+
+    /// The newest matching version, given `versions` in the API's ascending order.
+    pub fn latest<'a>(&self, versions: &'a [String]) -> Option<&'a String> {
+        versions.iter().rev().find(|version| self.matches(version))
+    }
+}
+
+impl FromStr for VersionSpec {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec.eq_ignore_ascii_case("latest") {
+            return Ok(VersionSpec::Latest);
+        }
+        if spec == "*" || spec == "x" {
+            return Ok(VersionSpec::Prefix(Vec::new()));
+        }
+
+        let components: Vec<&str> = spec.split('.').collect();
+        let wildcard = |component: &&str| *component == "x" || *component == "*";
+        match components.iter().position(wildcard) {
+            Some(position) if position == components.len() - 1 => {
+                Ok(VersionSpec::Prefix(components[..position].iter().map(|c| c.to_string()).collect()))
+            }
+            Some(_) => Err(Box::from(format!("Wildcards must be the last component of \"{}\".", spec))),
+            None => {
+                super::paper::validate_identifier(spec)?;
+                Ok(VersionSpec::Exact(spec.to_string()))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for VersionSpec {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn try_from(spec: String) -> Result<Self> {
+        spec.parse()
+    }
+}
+
+impl From<VersionSpec> for String {
+    fn from(spec: VersionSpec) -> Self {
+        spec.to_string()
+    }
+}
+
+impl fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionSpec::Latest => f.write_str("latest"),
+            VersionSpec::Exact(version) => f.write_str(version),
+            VersionSpec::Prefix(prefix) if prefix.is_empty() => f.write_str("*"),
+            VersionSpec::Prefix(prefix) => write!(f, "{}.x", prefix.join(".")),
+        }
+    }
+}
+
+/// The version group a version belongs to, e.g. `1.16` for `1.16.5`.
+pub fn group_of<'a>(version: &str, groups: &'a [String]) -> Option<&'a String> {
+    groups.iter()
+        .filter(|group| version == group.as_str() || version.starts_with(&format!("{}.", group)))
+        .max_by_key(|group| group.len())
+}
+
+/// The sort key of a version component: numbers by value, others such as `pre10` by their text
+/// and then their trailing number.
+fn component_key(component: &str) -> (&str, Option<u64>, &str) {
+    let digits = component.find(|c: char| c.is_ascii_digit()).unwrap_or(component.len());
+    (&component[..digits], component[digits..].parse().ok(), component)
+}
+
+/// Splits a version such as `1.17-pre1` into its release and pre-release suffix.
+pub fn split_pre_release(version: &str) -> (&str, Option<&str>) {
+    match version.split_once('-') {
+        Some((release, suffix)) => (release, Some(suffix)),
+        None => (version, None),
+    }
+}
+
+/// Orders versions by their numeric components, e.g. `1.9` before `1.16.5`. A suffix after `-`
+/// marks a pre-release, which orders before its release, e.g. `1.17-pre1` before `1.17-rc1`
+/// before `1.17`.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let ((a_release, a_suffix), (b_release, b_suffix)) = (split_pre_release(a), split_pre_release(b));
+    a_release.split('.').map(component_key).cmp(b_release.split('.').map(component_key))
+        .then_with(|| match (a_suffix, b_suffix) {
+            (Some(a), Some(b)) => a.split(['.', '-']).map(component_key).cmp(b.split(['.', '-']).map(component_key)),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        })
+}
@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::time::Duration;
+
+use futures_util::Stream;
+use serde::{Serialize, Deserialize};
+
+use super::download::write_atomic;
+use super::paper::{BuildDownloadRequest, BuildInfo, ChangesInfo};
+use super::resolve::GroupBuilds;
+use super::version::VersionSpec;
+use super::{PaperClientConfig, Result};
+
+/// A build which appeared after the last one seen for its project and version.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewBuildEvent {
+    pub project: String,
+    pub version: String,
+    pub build: i32,
+    pub time: String,
+    pub download_name: String,
+    pub download_url: String,
+    pub sha256: String,
+    pub changes: Vec<ChangesInfo>,
+}
+
+impl NewBuildEvent {
+    pub fn from_build_info<ClientConfig>(project: &str, info: &BuildInfo) -> Self where ClientConfig: PaperClientConfig {
+        let application = &info.downloads.application;
+        Self {
+            project: project.to_string(),
+            version: info.version.clone(),
+            build: info.build,
+            time: info.time.clone(),
+            download_name: application.name.clone(),
+            download_url: BuildDownloadRequest::new(project, &info.version, info.build, &application.name)
+                .download_url::<ClientConfig>(),
+            sha256: application.sha256.clone(),
+            changes: info.changes.clone(),
+        }
+    }
+
+    /// The environment passed to hooks, e.g. `PAPER_BUILD=794`.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("PAPER_PROJECT", self.project.clone()),
+            ("PAPER_VERSION", self.version.clone()),
+            ("PAPER_BUILD", self.build.to_string()),
+            ("PAPER_BUILD_TIME", self.time.clone()),
+            ("PAPER_DOWNLOAD_NAME", self.download_name.clone()),
+            ("PAPER_DOWNLOAD_URL", self.download_url.clone()),
+            ("PAPER_SHA256", self.sha256.clone()),
+        ]
+    }
+}
+
+/// The last build seen per project and version, persisted between runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct WatchState {
+    pub last_builds: BTreeMap<String, i32>,
+}
+
+impl WatchState {
+    fn key(project: &str, version: &str) -> String {
+        format!("{}/{}", project, version)
+    }
+
+    /// Loads the state at `path`, starting empty when it does not exist yet.
+    pub fn load<P>(path: P) -> Result<Self> where P: AsRef<Path> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Box::from(e)),
+        }
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()> where P: AsRef<Path> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn last_build(&self, project: &str, version: &str) -> Option<i32> {
+        self.last_builds.get(&Self::key(project, version)).copied()
+    }
+
+    pub fn set_last_build(&mut self, project: &str, version: &str, build: i32) {
+        let last = self.last_builds.entry(Self::key(project, version)).or_insert(build);
+        *last = build.max(*last);
+    }
+}
+
+pub async fn build_event<ClientConfig>(project: &str, version: &str, build: i32) -> Result<NewBuildEvent>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let info = ClientConfig::get_version_builds(project, version, build).await?;
+    let application = info.downloads.application;
+    let download_url = BuildDownloadRequest::new(project, version, build, &application.name)
+        .download_url::<ClientConfig>();
+    Ok(NewBuildEvent {
+        project: project.to_string(),
+        version: version.to_string(),
+        build,
+        time: info.time,
+        download_name: application.name,
+        download_url,
+        sha256: application.sha256,
+        changes: info.changes,
+    })
+}
+
+/// The builds of `version` newer than `after`, oldest first.
+pub async fn new_builds<ClientConfig>(project: &str, version: &str, after: Option<i32>) -> Result<Vec<NewBuildEvent>>
+    where ClientConfig: PaperClientConfig + Send
+{
+    let info = ClientConfig::get_version_info(project, version).await?;
+    let mut builds: Vec<i32> = info.builds.into_iter()
+        .filter(|build| after.map_or(true, |after| *build > after))
+        .collect();
+    builds.sort_unstable();
+
+    let mut events = Vec::with_capacity(builds.len());
+    for build in builds {
+        events.push(build_event::<ClientConfig>(project, version, build).await?);
+    }
+    Ok(events)
+}
+
+/// Polls the versions of a project matching a [`VersionSpec`] for builds newer than its cursor.
+///
+/// Versions without a cursor entry on the first poll start at their newest build, unless
+/// `emit_existing` is set. Versions which appear later, such as a new `1.16.x` release, are
+/// reported from their first build.
+pub struct BuildWatch {
+    project: String,
+    spec: VersionSpec,
+    interval: Duration,
+    cursor: WatchState,
+    emit_existing: bool,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    initialized: bool,
+}
+
+impl BuildWatch {
+    pub fn new<T>(project: T, spec: VersionSpec, interval: Duration) -> Self where T: Into<String> {
+        Self {
+            project: project.into(),
+            spec,
+            interval,
+            cursor: WatchState::default(),
+            emit_existing: false,
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: interval.max(Duration::from_secs(5)),
+            initialized: false,
+        }
+    }
+
+    /// Resumes from a previously persisted cursor.
+    pub fn cursor(mut self, cursor: WatchState) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn emit_existing(mut self, emit_existing: bool) -> Self {
+        self.emit_existing = emit_existing;
+        self
+    }
+
+    /// Failed polls are retried after `initial`, doubling up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn current_cursor(&self) -> &WatchState {
+        &self.cursor
+    }
+
+    pub fn cursor_mut(&mut self) -> &mut WatchState {
+        &mut self.cursor
+    }
+
+    fn retry_delay(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1).min(16));
+        self.initial_backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+    }
+
+    /// Polls once, returning the new builds oldest first. The cursor is only advanced for
+    /// versions seen for the first time, callers advance it as they handle each event.
+    pub async fn poll<ClientConfig>(&mut self) -> Result<Vec<NewBuildEvent>>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let project = ClientConfig::get_project(self.project.as_str()).await?;
+        let mut group_builds = GroupBuilds::new(&project);
+        let mut events = Vec::new();
+
+        for version in self.spec.select(&project.versions) {
+            let info = ClientConfig::get_version_info(project.project_id.as_str(), version).await?;
+            let last = self.cursor.last_build(&project.project_id, version);
+            if last.is_none() && !self.initialized && !self.emit_existing {
+                if let Some(newest) = info.builds.iter().max() {
+                    self.cursor.set_last_build(&project.project_id, version, *newest);
+                }
+                continue;
+            }
+
+            let mut builds: Vec<i32> = info.builds.into_iter()
+                .filter(|build| last.map_or(true, |last| *build > last))
+                .collect();
+            builds.sort_unstable();
+            builds.dedup();
+            if builds.is_empty() {
+                continue;
+            }
+
+            for info in group_builds.builds::<ClientConfig>(version, &builds).await? {
+                events.push(NewBuildEvent::from_build_info::<ClientConfig>(&project.project_id, &info));
+            }
+        }
+
+        self.initialized = true;
+        Ok(events)
+    }
+
+    /// Polls every `interval` forever, yielding each new build once and backing off after errors.
+    pub fn stream<ClientConfig>(self) -> impl Stream<Item = Result<NewBuildEvent>>
+        where ClientConfig: PaperClientConfig + Send
+    {
+        let state = (self, VecDeque::<NewBuildEvent>::new(), false, 0u32);
+        futures_util::stream::unfold(state, |(mut watch, mut queue, mut polled, mut failures)| async move {
+            loop {
+                if let Some(event) = queue.pop_front() {
+                    watch.cursor.set_last_build(&event.project, &event.version, event.build);
+                    return Some((Ok(event), (watch, queue, polled, failures)));
+                }
+                if polled {
+                    let delay = if failures > 0 { watch.retry_delay(failures) } else { watch.interval };
+                    tokio::time::delay_for(delay).await;
+                }
+                polled = true;
+                match watch.poll::<ClientConfig>().await {
+                    Ok(events) => {
+                        failures = 0;
+                        queue.extend(events);
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        return Some((Err(e), (watch, queue, polled, failures)));
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// A stream of the new builds of the versions of `project` matching `spec`, polled every `interval`.
+pub fn watch_builds<ClientConfig, T>(project: T, spec: VersionSpec, interval: Duration) -> impl Stream<Item = Result<NewBuildEvent>>
+    where ClientConfig: PaperClientConfig + Send,
+          T: Into<String>
+{
+    BuildWatch::new(project, spec, interval).stream::<ClientConfig>()
+}